@@ -0,0 +1,82 @@
+use crate::{lexer::Position, optimizer::Op};
+
+/// A single instruction of the flat bytecode, with loops resolved into jumps
+///
+/// Jump targets are indices into the bytecode, pointing right behind the matching jump.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bytecode {
+    /// `+` and `-`: Add a (wrapping) amount to the value of the current cell
+    Add(i64),
+    /// `>` and `<`: Move the data pointer by an amount of cells
+    Move(isize),
+    /// `.`: Output the value of the current cell
+    Output,
+    /// `,`: Replace the value of the current cell with input
+    Input,
+    /// `#`: Print the data pointer and the cells around it to stderr
+    Dump,
+    /// `[-]`: Set the value of the current cell to zero
+    Clear,
+    /// `[->+<]`: Add the value of the current cell multiplied by `factor` to the cell at `offset`
+    MulAdd { offset: isize, factor: i64 },
+    /// `[>]` and `[<<]`: Move the data pointer by `stride` cells until it points at a zero cell
+    Scan(isize),
+    /// `[`: Jump to the target if the value of the current cell is zero
+    JumpIfZero(usize),
+    /// `]`: Jump to the target if the value of the current cell is not zero
+    JumpIfNonZero(usize),
+}
+
+/// Flattens optimized operations into bytecode
+///
+/// The positions are returned separately from the bytecode to keep it compact, the position of
+/// each instruction is found at the same index.
+pub fn compile(ops: &[(Op, Position)]) -> (Vec<Bytecode>, Vec<Position>) {
+    let mut bytecode = vec![];
+    let mut positions = vec![];
+    let mut rest = ops.iter();
+
+    // The loops that are currently open, with the index of their `[`, the position of their `]`
+    // and the operations after them, so nested loops do not need recursion
+    let mut stack = vec![];
+
+    loop {
+        let Some((op, position)) = rest.next() else {
+            let Some((start, end, outer)) = stack.pop() else {
+                break;
+            };
+
+            bytecode.push(Bytecode::JumpIfNonZero(start + 1));
+            positions.push(end);
+            bytecode[start] = Bytecode::JumpIfZero(bytecode.len());
+            rest = outer;
+            continue;
+        };
+
+        let code = match op {
+            Op::Add(amount) => Bytecode::Add(*amount),
+            Op::Move(offset) => Bytecode::Move(*offset),
+            Op::Output => Bytecode::Output,
+            Op::Input => Bytecode::Input,
+            Op::Dump => Bytecode::Dump,
+            Op::Clear => Bytecode::Clear,
+            Op::MulAdd { offset, factor } => Bytecode::MulAdd {
+                offset: *offset,
+                factor: *factor,
+            },
+            Op::Scan(stride) => Bytecode::Scan(*stride),
+            Op::Loop(body, end) => {
+                stack.push((bytecode.len(), *end, rest));
+                rest = body.iter();
+
+                // The target is patched once the end of the loop is known
+                Bytecode::JumpIfZero(0)
+            }
+        };
+
+        bytecode.push(code);
+        positions.push(*position);
+    }
+
+    (bytecode, positions)
+}
@@ -0,0 +1,102 @@
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
+
+use crate::config::Overflow;
+
+/// The value stored in a single cell of the tape
+///
+/// Implemented for `u8`, `u16`, `u32` and `u64`. Arithmetic that leaves the range of the cell is
+/// handled according to the [`Overflow`] behavior.
+///
+/// The trait is sealed, since compiled code relies on [`BITS`](Cell::BITS) being the size of the
+/// type.
+pub trait Cell: sealed::Sealed + Copy + Default + Eq + Debug + Display + FromStr + 'static {
+    /// The width of the cell in bits
+    const BITS: u32;
+
+    /// The largest value of the cell, which is -1 in two's complement
+    const MAX: Self;
+
+    /// Adds an amount to the cell, returning `None` if it overflows and overflow traps
+    fn add(self, amount: i64, overflow: Overflow) -> Option<Self>;
+
+    /// Adds `value` multiplied by `factor` to the cell, returning `None` if it overflows and
+    /// overflow traps
+    fn mul_add(self, value: Self, factor: i64, overflow: Overflow) -> Option<Self>;
+
+    /// Creates a cell from a byte that was read as input
+    fn from_byte(byte: u8) -> Self;
+
+    /// The lowest byte of the cell, which is written as output
+    fn to_byte(self) -> u8;
+
+    /// Whether the cell is zero
+    fn is_zero(self) -> bool {
+        self == Self::default()
+    }
+}
+
+mod sealed {
+    /// Keeps [`Cell`](super::Cell) from being implemented outside of this crate
+    pub trait Sealed {}
+}
+
+macro_rules! impl_cell {
+    ($($ty:ty),*) => {
+        $(
+            impl sealed::Sealed for $ty {}
+
+            impl Cell for $ty {
+                const BITS: u32 = <$ty>::BITS;
+                const MAX: Self = <$ty>::MAX;
+
+                #[inline]
+                fn add(self, amount: i64, overflow: Overflow) -> Option<Self> {
+                    match overflow {
+                        // Truncating the amount keeps it the same modulo the width of the cell
+                        Overflow::Wrap => Some(self.wrapping_add(amount as $ty)),
+                        _ => fit(self as i128 + amount as i128, <$ty>::MAX, overflow),
+                    }
+                }
+
+                #[inline]
+                fn mul_add(self, value: Self, factor: i64, overflow: Overflow) -> Option<Self> {
+                    match overflow {
+                        Overflow::Wrap => Some(self.wrapping_add(value.wrapping_mul(factor as $ty))),
+                        _ => fit(
+                            (value as i128)
+                                .saturating_mul(factor as i128)
+                                .saturating_add(self as i128),
+                            <$ty>::MAX,
+                            overflow,
+                        ),
+                    }
+                }
+
+                #[inline]
+                fn from_byte(byte: u8) -> Self {
+                    byte as $ty
+                }
+
+                #[inline]
+                fn to_byte(self) -> u8 {
+                    self as u8
+                }
+            }
+        )*
+    };
+}
+
+impl_cell!(u8, u16, u32, u64);
+
+/// Fits a value into a cell with the given maximum, saturating or trapping if it does not fit
+fn fit<C: TryFrom<i128> + Default>(value: i128, max: C, overflow: Overflow) -> Option<C> {
+    match C::try_from(value) {
+        Ok(cell) => Some(cell),
+        Err(_) if overflow == Overflow::Trap => None,
+        Err(_) if value < 0 => Some(C::default()),
+        Err(_) => Some(max),
+    }
+}
@@ -0,0 +1,562 @@
+use bfi::{codegen::wasm::Interface, Boundary, Config, Eof, Extensions, Overflow};
+
+use super::{
+    compile::{CompileOptions, Target},
+    debug::DebugOptions,
+    run::{Input, RunOptions},
+    Error, Source,
+};
+
+/// The options of every command that runs a program on a machine
+macro_rules! machine_options {
+    () => {
+        "    --tape-size <cells>  amount of cells on the tape (default: 24576)
+    --start <cell>       index of the cell the data pointer starts at (default: the middle)
+    --cell-size <bits>   width of each cell: 8 (default), 16, 32 or 64
+    --boundary <mode>    what happens when the data pointer moves past either end of the tape:
+                         `error` (default), `wrap` or `grow`
+    --overflow <mode>    what happens when a cell is incremented past its maximum or decremented
+                         past zero: `wrap` (default), `saturate` or `trap`
+    --eof <mode>         what happens to the current cell when reading after the end of the input:
+                         `unchanged` (default), `zero` or `minus-one`
+"
+    };
+}
+
+const USAGE: &str = "bfi - Brainfuck Interpreter written in Rust
+
+usage: bfi [command] [options] <file>
+
+commands:
+    run      run a program (default)
+    check    check a program for errors without running it
+    fmt      print a program formatted and without comments
+    repl     run code interactively, line by line on the same tape
+    debug    step through a program with breakpoints
+    compile  compile a program to another language
+
+A file of `-` reads the program from stdin, `-e <code>` uses code given on the command line.
+Run `bfi <command> --help` for the options of each command.
+
+options:
+    -h, --help     print help
+    -V, --version  print version
+
+exit codes:
+    0  success
+    1  the program could not be parsed
+    2  the command line arguments are invalid
+    3  the program failed while running
+    4  reading or writing a file, input or output failed";
+
+const RUN_USAGE: &str = concat!(
+    "usage: bfi run [options] <file>
+       bfi run [options] -e <code>
+       bfi run [options] -
+
+A file of `-` reads the program from stdin, anything after the first `!` is used as input.
+
+options:
+    -e <code>            run code given on the command line
+",
+    machine_options!(),
+    "    --input <text>       use text as input, supporting the escapes \\n, \\r, \\t, \\0, \\\\ and \\xHH
+    --input-file <path>  use the contents of a file as input
+    --stdin              continue reading from stdin after the given input
+    --dump               treat `#` as an instruction that prints the data pointer and the cells
+                         around it to stderr
+    --trace <path>       write every executed instruction to a file as JSON lines
+    --profile            report the lines, spans and loops that ran the most and the annotated
+                         source code to stderr
+    -h, --help           print help
+
+If multiple inputs are given, they are read one after another. Without any input, stdin is read."
+);
+
+const REPL_USAGE: &str = concat!(
+    "usage: bfi repl [options]
+
+Runs each entered line of code on the same tape. Lines starting with `:` are commands, enter
+`:help` to list them.
+
+options:
+",
+    machine_options!(),
+    "    --dump               treat `#` as an instruction that prints the data pointer and the cells
+                         around it to stderr
+    -h, --help           print help"
+);
+
+const DEBUG_USAGE: &str = concat!(
+    "usage: bfi debug [options] <file>
+       bfi debug [options] -e <code>
+
+Runs a program instruction by instruction, controlled by commands read from stdin. Enter `help`
+to list them.
+
+options:
+    -e <code>            debug code given on the command line
+",
+    machine_options!(),
+    "    --input <text>       use text as input, supporting the escapes \\n, \\r, \\t, \\0, \\\\ and \\xHH
+    --input-file <path>  use the contents of a file as input
+    -h, --help           print help
+
+If multiple inputs are given, they are read one after another. Without any input, the program
+reads from stdin, just like the commands."
+);
+
+const COMPILE_USAGE: &str = concat!(
+    "usage: bfi compile --target <target> [options] <file>
+       bfi compile --target <target> [options] -e <code>
+       bfi compile --target <target> [options] -
+
+Compiles a program to another language or an executable, which behaves like running it with the
+given options.
+
+options:
+    --target <target>    what to compile to: `c`, `rust`, `rust-module`, `x86_64-linux`, `wasm`,
+                         `wat`, `wasm-wasi` or `wat-wasi`
+    -o, --output <path>  write the compiled program to a file instead of stdout
+    -e <code>            compile code given on the command line
+",
+    machine_options!(),
+    "    --dump               treat `#` as an instruction that prints the data pointer and the cells
+                         around it to stderr
+    -h, --help           print help"
+);
+
+/// The names of the targets of `bfi compile`
+const TARGETS: &[(&str, Target)] = &[
+    ("c", Target::C),
+    ("rust", Target::Rust),
+    ("rust-module", Target::RustModule),
+    ("x86_64-linux", Target::X86_64Linux),
+    ("wasm", Target::Wasm(Interface::Host)),
+    ("wat", Target::Wat(Interface::Host)),
+    ("wasm-wasi", Target::Wasm(Interface::Wasi)),
+    ("wat-wasi", Target::Wat(Interface::Wasi)),
+];
+
+const CHECK_USAGE: &str = "usage: bfi check [options] <file>
+
+Checks a program for errors without running it.
+
+options:
+    -e <code>   check code given on the command line
+    -h, --help  print help";
+
+const FMT_USAGE: &str = "usage: bfi fmt [options] <file>
+
+Prints a program formatted and without comments to stdout.
+
+options:
+    -e <code>   format code given on the command line
+    --dump      keep `#` as an instruction instead of removing it as a comment
+    -h, --help  print help";
+
+/// The settings of the machine a program runs on
+pub struct MachineOptions {
+    pub config: Config,
+    /// The width of each cell in bits
+    pub cell_size: u32,
+}
+
+impl Default for MachineOptions {
+    fn default() -> Self {
+        Self {
+            config: Config::default(),
+            cell_size: 8,
+        }
+    }
+}
+
+/// What the command line asks to do
+pub enum Command {
+    /// Print a help text
+    Help(&'static str),
+    /// Print the version
+    Version,
+    Run(RunOptions),
+    Check(Source),
+    Fmt(Source, Extensions),
+    Repl(MachineOptions, Extensions),
+    Debug(DebugOptions),
+    Compile(CompileOptions),
+}
+
+/// The command line arguments that are left to parse
+struct Args<I: Iterator<Item = String>> {
+    args: I,
+}
+
+impl<I: Iterator<Item = String>> Args<I> {
+    fn value(&mut self, flag: &str) -> Result<String, Error> {
+        self.args
+            .next()
+            .ok_or_else(|| Error::Usage(format!("missing value for `{flag}`")))
+    }
+
+    fn number(&mut self, flag: &str) -> Result<usize, Error> {
+        let value = self.value(flag)?;
+        value.parse().map_err(|_| invalid_value(flag, &value))
+    }
+
+    fn choice<T: Copy>(&mut self, flag: &str, choices: &[(&str, T)]) -> Result<T, Error> {
+        let value = self.value(flag)?;
+        choices
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, choice)| *choice)
+            .ok_or_else(|| invalid_value(flag, &value))
+    }
+
+    /// Parses the arguments that name the program, returning `false` if the argument is none of them
+    fn source(&mut self, arg: &str, source: &mut Option<Source>) -> Result<bool, Error> {
+        let parsed = match arg {
+            "-e" => Source::Inline(self.value(arg)?),
+            "-" => Source::Stdin,
+            flag if flag.starts_with('-') => return Ok(false),
+            path => Source::File(path.to_string()),
+        };
+
+        if source.is_some() {
+            return Err(Error::Usage("only one program can be given".to_string()));
+        }
+        *source = Some(parsed);
+
+        Ok(true)
+    }
+}
+
+fn invalid_value(flag: &str, value: &str) -> Error {
+    Error::Usage(format!("invalid value `{value}` for `{flag}`"))
+}
+
+fn unknown_option(flag: &str) -> Error {
+    Error::Usage(format!("unknown option `{flag}`"))
+}
+
+fn missing_program() -> Error {
+    Error::Usage("missing program".to_string())
+}
+
+/// Turns escape sequences in text given on the command line into the bytes they represent
+fn unescape(text: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![];
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+
+        let invalid = |sequence: String| {
+            Error::Usage(format!("invalid escape sequence `\\{sequence}` in input"))
+        };
+
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 => bytes.push(byte),
+                    _ => return Err(invalid(format!("x{digits}"))),
+                }
+            }
+            Some(c) => return Err(invalid(c.to_string())),
+            None => return Err(invalid(String::new())),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Parses the command line arguments, without the name of the binary
+pub fn parse(args: impl Iterator<Item = String>) -> Result<Command, Error> {
+    let mut args = args.peekable();
+
+    let command = match args.peek().map(String::as_str) {
+        Some("-h" | "--help") => return Ok(Command::Help(USAGE)),
+        Some("-V" | "--version") => return Ok(Command::Version),
+        Some(command @ ("run" | "check" | "fmt" | "repl" | "debug" | "compile")) => {
+            let command = command.to_string();
+            args.next();
+            command
+        }
+        // Running is the default, so `bfi <file>` keeps working
+        _ => "run".to_string(),
+    };
+
+    let mut args = Args { args };
+
+    match command.as_str() {
+        "check" => parse_source_only(&mut args, CHECK_USAGE, Command::Check),
+        "fmt" => parse_fmt(&mut args),
+        "repl" => parse_repl(&mut args),
+        "debug" => parse_debug(&mut args),
+        "compile" => parse_compile(&mut args),
+        _ => parse_run(&mut args),
+    }
+}
+
+/// Parses the arguments of a command that only takes a program
+fn parse_source_only(
+    args: &mut Args<impl Iterator<Item = String>>,
+    usage: &'static str,
+    command: fn(Source) -> Command,
+) -> Result<Command, Error> {
+    let mut source = None;
+
+    while let Some(arg) = args.args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(usage)),
+            _ if args.source(&arg, &mut source)? => (),
+            flag => return Err(unknown_option(flag)),
+        }
+    }
+
+    Ok(command(source.ok_or_else(missing_program)?))
+}
+
+fn parse_fmt(args: &mut Args<impl Iterator<Item = String>>) -> Result<Command, Error> {
+    let mut source = None;
+    let mut extensions = Extensions::default();
+
+    while let Some(arg) = args.args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(FMT_USAGE)),
+            "--dump" => extensions.dump = true,
+            _ if args.source(&arg, &mut source)? => (),
+            flag => return Err(unknown_option(flag)),
+        }
+    }
+
+    Ok(Command::Fmt(
+        source.ok_or_else(missing_program)?,
+        extensions,
+    ))
+}
+
+/// Parses an option that configures the machine, returning `false` if the argument is none of them
+fn machine_option(
+    args: &mut Args<impl Iterator<Item = String>>,
+    arg: &str,
+    options: &mut MachineOptions,
+    start: &mut Option<usize>,
+) -> Result<bool, Error> {
+    let config = &mut options.config;
+
+    match arg {
+        "--tape-size" => config.tape_size = args.number(arg)?,
+        "--start" => *start = Some(args.number(arg)?),
+        "--cell-size" => {
+            options.cell_size = args.choice(arg, &[("8", 8), ("16", 16), ("32", 32), ("64", 64)])?
+        }
+        "--boundary" => {
+            config.boundary = args.choice(
+                arg,
+                &[
+                    ("error", Boundary::Error),
+                    ("wrap", Boundary::Wrap),
+                    ("grow", Boundary::Grow),
+                ],
+            )?
+        }
+        "--overflow" => {
+            config.overflow = args.choice(
+                arg,
+                &[
+                    ("wrap", Overflow::Wrap),
+                    ("saturate", Overflow::Saturate),
+                    ("trap", Overflow::Trap),
+                ],
+            )?
+        }
+        "--eof" => {
+            config.eof = args.choice(
+                arg,
+                &[
+                    ("unchanged", Eof::Unchanged),
+                    ("zero", Eof::Zero),
+                    ("minus-one", Eof::MinusOne),
+                ],
+            )?
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+/// Places the data pointer at the given start or in the middle of the tape
+fn place_start(options: &mut MachineOptions, start: Option<usize>) -> Result<(), Error> {
+    let config = &mut options.config;
+    config.start = start.unwrap_or(config.tape_size / 2);
+
+    if config.start >= config.tape_size && config.boundary != Boundary::Grow {
+        return Err(Error::Usage(format!(
+            "start cell {} is outside of the tape of {} cells",
+            config.start, config.tape_size
+        )));
+    }
+
+    // The tape grows to hold the start, and no allocation can be larger than `isize::MAX` bytes
+    let allocatable = config
+        .start
+        .checked_add(1)
+        .map(|end| end.max(config.tape_size))
+        .and_then(|cells| cells.checked_mul(options.cell_size as usize / 8))
+        .is_some_and(|bytes| bytes <= isize::MAX as usize);
+    if !allocatable {
+        return Err(Error::Usage(format!(
+            "a tape of {} cells starting at cell {} is too large",
+            config.tape_size, config.start
+        )));
+    }
+
+    Ok(())
+}
+
+fn parse_run(args: &mut Args<impl Iterator<Item = String>>) -> Result<Command, Error> {
+    let mut source = None;
+    let mut machine = MachineOptions::default();
+    let mut start = None;
+    let mut extensions = Extensions::default();
+    let mut inputs = vec![];
+    let mut stdin = false;
+    let mut trace = None;
+    let mut profile = false;
+
+    while let Some(arg) = args.args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(RUN_USAGE)),
+            "--dump" => extensions.dump = true,
+            "--input" => inputs.push(Input::Text(unescape(&args.value(&arg)?)?)),
+            "--input-file" => inputs.push(Input::File(args.value(&arg)?)),
+            "--stdin" => stdin = true,
+            "--trace" => trace = Some(args.value(&arg)?),
+            "--profile" => profile = true,
+            _ if machine_option(args, &arg, &mut machine, &mut start)? => (),
+            _ if args.source(&arg, &mut source)? => (),
+            flag => return Err(unknown_option(flag)),
+        }
+    }
+
+    place_start(&mut machine, start)?;
+
+    if trace.is_some() && profile {
+        return Err(Error::Usage(
+            "`--trace` and `--profile` cannot be used together".to_string(),
+        ));
+    }
+
+    Ok(Command::Run(RunOptions {
+        source: source.ok_or_else(missing_program)?,
+        machine,
+        extensions,
+        trace,
+        profile,
+        stdin: stdin || inputs.is_empty(),
+        inputs,
+    }))
+}
+
+fn parse_repl(args: &mut Args<impl Iterator<Item = String>>) -> Result<Command, Error> {
+    let mut machine = MachineOptions::default();
+    let mut start = None;
+    let mut extensions = Extensions::default();
+
+    while let Some(arg) = args.args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(REPL_USAGE)),
+            "--dump" => extensions.dump = true,
+            _ if machine_option(args, &arg, &mut machine, &mut start)? => (),
+            flag => return Err(unknown_option(flag)),
+        }
+    }
+
+    place_start(&mut machine, start)?;
+
+    Ok(Command::Repl(machine, extensions))
+}
+
+fn parse_debug(args: &mut Args<impl Iterator<Item = String>>) -> Result<Command, Error> {
+    let mut source = None;
+    let mut machine = MachineOptions::default();
+    let mut start = None;
+    let mut inputs = vec![];
+
+    while let Some(arg) = args.args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(DEBUG_USAGE)),
+            "--input" => inputs.push(Input::Text(unescape(&args.value(&arg)?)?)),
+            "--input-file" => inputs.push(Input::File(args.value(&arg)?)),
+            // Commands are read from stdin, so the program cannot be
+            "-" => {
+                return Err(Error::Usage(
+                    "the program to debug cannot be read from stdin".to_string(),
+                ))
+            }
+            _ if machine_option(args, &arg, &mut machine, &mut start)? => (),
+            _ if args.source(&arg, &mut source)? => (),
+            flag => return Err(unknown_option(flag)),
+        }
+    }
+
+    place_start(&mut machine, start)?;
+
+    Ok(Command::Debug(DebugOptions {
+        source: source.ok_or_else(missing_program)?,
+        machine,
+        inputs,
+    }))
+}
+
+fn parse_compile(args: &mut Args<impl Iterator<Item = String>>) -> Result<Command, Error> {
+    let mut source = None;
+    let mut machine = MachineOptions::default();
+    let mut start = None;
+    let mut extensions = Extensions::default();
+    let mut target = None;
+    let mut output = None;
+
+    while let Some(arg) = args.args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(COMPILE_USAGE)),
+            "--target" => target = Some(args.choice(&arg, TARGETS)?),
+            "-o" | "--output" => output = Some(args.value(&arg)?),
+            "--dump" => extensions.dump = true,
+            _ if machine_option(args, &arg, &mut machine, &mut start)? => (),
+            _ if args.source(&arg, &mut source)? => (),
+            flag => return Err(unknown_option(flag)),
+        }
+    }
+
+    place_start(&mut machine, start)?;
+
+    let target = target.ok_or_else(|| Error::Usage("missing `--target`".to_string()))?;
+    if extensions.dump && !target.supports_dump() {
+        let (name, _) = TARGETS
+            .iter()
+            .find(|(_, choice)| *choice == target)
+            .unwrap();
+        return Err(Error::Usage(format!(
+            "`--dump` is not supported by the `{name}` target"
+        )));
+    }
+
+    Ok(Command::Compile(CompileOptions {
+        source: source.ok_or_else(missing_program)?,
+        target,
+        machine,
+        extensions,
+        output,
+    }))
+}
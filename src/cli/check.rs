@@ -0,0 +1,10 @@
+use bfi::Extensions;
+
+use super::{Error, Source};
+
+/// Parses a program without running it, only reporting errors
+pub fn check(source: Source) -> Result<(), Error> {
+    source.load()?.parse(Extensions::default())?;
+
+    Ok(())
+}
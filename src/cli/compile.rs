@@ -0,0 +1,117 @@
+use std::{
+    fs,
+    io::{self, IsTerminal, Write},
+};
+
+use bfi::{
+    codegen::{self, wasm::Interface},
+    Cell, Config, Extensions, Program,
+};
+
+use super::{args::MachineOptions, Error, Source};
+
+/// The language or platform a program is compiled to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    C,
+    /// A standalone Rust program
+    Rust,
+    /// A Rust module to embed into another program
+    RustModule,
+    /// A Linux executable for x86-64
+    X86_64Linux,
+    /// A binary WebAssembly module
+    Wasm(Interface),
+    /// A WebAssembly module in the text format
+    Wat(Interface),
+}
+
+impl Target {
+    /// Whether the target produces an executable instead of source code
+    fn is_executable(self) -> bool {
+        self == Target::X86_64Linux
+    }
+
+    /// Whether the target produces binary code instead of text
+    fn is_binary(self) -> bool {
+        matches!(self, Target::X86_64Linux | Target::Wasm(_))
+    }
+
+    /// Whether the compiled program can dump the tape
+    pub fn supports_dump(self) -> bool {
+        matches!(self, Target::C | Target::Rust | Target::RustModule)
+    }
+}
+
+pub struct CompileOptions {
+    pub source: Source,
+    pub machine: MachineOptions,
+    pub extensions: Extensions,
+    pub target: Target,
+    /// The file the compiled program is written to instead of stdout
+    pub output: Option<String>,
+}
+
+/// Compiles a program to another language
+pub fn compile(options: CompileOptions) -> Result<(), Error> {
+    if options.target.is_binary() && options.output.is_none() && io::stdout().is_terminal() {
+        return Err(Error::Usage(
+            "refusing to write binary code to a terminal, use `-o <path>`".to_string(),
+        ));
+    }
+
+    let source = options.source.load()?;
+    let program = source.parse_with_config(options.extensions, &options.machine.config)?;
+
+    let config = &options.machine.config;
+    let code = match options.machine.cell_size {
+        8 => generate::<u8>(options.target, &program, config),
+        16 => generate::<u16>(options.target, &program, config),
+        32 => generate::<u32>(options.target, &program, config),
+        _ => generate::<u64>(options.target, &program, config),
+    }
+    .map_err(|e| Error::Usage(e.to_string()))?;
+
+    match options.output {
+        Some(path) => write_file(&path, &code, options.target.is_executable())
+            .map_err(|e| Error::io(format_args!("failed to write output file `{path}`"), e)),
+        None => io::stdout()
+            .lock()
+            .write_all(&code)
+            .map_err(|e| Error::io("failed to write output", e)),
+    }
+}
+
+fn generate<C: Cell>(
+    target: Target,
+    program: &Program,
+    config: &Config,
+) -> Result<Vec<u8>, codegen::wasm::Error> {
+    let code = match target {
+        Target::C => codegen::c::transpile::<C>(program, config).into_bytes(),
+        Target::Rust => codegen::rust::transpile_program::<C>(program, config).into_bytes(),
+        Target::RustModule => codegen::rust::transpile::<C>(program, config).into_bytes(),
+        Target::X86_64Linux => codegen::x86_64::compile::<C>(program, config),
+        Target::Wasm(interface) => codegen::wasm::compile::<C>(program, config, interface)?,
+        Target::Wat(interface) => {
+            codegen::wasm::transpile::<C>(program, config, interface)?.into_bytes()
+        }
+    };
+
+    Ok(code)
+}
+
+/// Writes the compiled program to a file, which is made executable if it is an executable
+fn write_file(path: &str, code: &[u8], executable: bool) -> io::Result<()> {
+    fs::write(path, code)?;
+
+    #[cfg(unix)]
+    if executable {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(not(unix))]
+    let _ = executable;
+
+    Ok(())
+}
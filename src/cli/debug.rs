@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, Read, Write},
+};
+
+use bfi::{error::snippet, Bytecode, Cell, Config, Machine, Position, Program, Status};
+
+use super::{
+    args::MachineOptions,
+    print_pointer, print_tape,
+    run::{chain_inputs, Input},
+    Error, LastByte, LoadedSource, Source,
+};
+
+const HELP: &str =
+    "The program is paused before the instruction that is shown, an empty line repeats the
+last command.
+
+commands:
+    step [<count>], s            run one or more instructions
+    continue, c                  run until a breakpoint is reached or the program ends
+    finish, f                    run until the innermost loop around the next instruction exits
+    break <line>[:<column>], b   pause before the (first) instruction at a line and column
+    delete <line>[:<column>], d  remove a breakpoint
+    breakpoints                  list all breakpoints
+    where, w                     show the next instruction
+    tape [<from> <to>]           show the cells around the data pointer or in a range
+    pointer [<cell>]             show the data pointer or move it to a cell
+    set <cell> <value>           change the value of a cell
+    restart                      clear the tape and run the program from the start
+    help                         show this help
+    quit, q                      exit the debugger";
+
+pub struct DebugOptions {
+    pub source: Source,
+    pub machine: MachineOptions,
+    /// The input of the program, which is read from stdin along with the commands if empty
+    pub inputs: Vec<Input>,
+}
+
+/// Runs a program step by step, controlled by commands read from stdin
+pub fn debug(options: DebugOptions) -> Result<(), Error> {
+    let source = options.source.load()?;
+    let program = Program::parse_unoptimized(&source.code).map_err(|e| Error::parse(e, &source))?;
+
+    let input: Box<dyn Read> = match options.inputs.is_empty() {
+        true => Box::new(io::stdin()),
+        false => chain_inputs(options.inputs)?,
+    };
+
+    let config = options.machine.config;
+    match options.machine.cell_size {
+        8 => Debugger::<u8>::new(source, program, config, input).run(),
+        16 => Debugger::<u16>::new(source, program, config, input).run(),
+        32 => Debugger::<u32>::new(source, program, config, input).run(),
+        _ => Debugger::<u64>::new(source, program, config, input).run(),
+    }
+}
+
+/// Where the run of the program is at
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Paused,
+    Finished,
+    Failed,
+}
+
+struct Debugger<C: Cell> {
+    source: LoadedSource,
+    program: Program,
+    config: Config,
+    machine: Machine<C>,
+    input: Box<dyn Read>,
+    /// The index of the next bytecode instruction to run
+    instruction_pointer: usize,
+    /// The bytecode instructions to pause before
+    breakpoints: BTreeSet<usize>,
+    state: State,
+}
+
+impl<C: Cell> Debugger<C> {
+    fn new(source: LoadedSource, program: Program, config: Config, input: Box<dyn Read>) -> Self {
+        Self {
+            source,
+            program,
+            machine: Machine::with_config(config.clone()),
+            config,
+            input,
+            instruction_pointer: 0,
+            breakpoints: BTreeSet::new(),
+            state: State::Paused,
+        }
+    }
+
+    fn run(&mut self) -> Result<(), Error> {
+        let mut line = String::new();
+        let mut last = String::new();
+
+        self.show_state();
+
+        loop {
+            print!("debug> ");
+            io::stdout()
+                .flush()
+                .map_err(|e| Error::io("failed to write output", e))?;
+
+            line.clear();
+            let read = io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|e| Error::io("failed to read stdin", e))?;
+
+            // End of input quits, like `quit`
+            if read == 0 {
+                println!();
+                return Ok(());
+            }
+
+            let command = match line.trim() {
+                "" => last.clone(),
+                command => command.to_string(),
+            };
+
+            if !self.command(&command)? {
+                return Ok(());
+            }
+            last = command;
+        }
+    }
+
+    /// Executes a command, returning `false` if the debugger should exit
+    fn command(&mut self, command: &str) -> Result<bool, Error> {
+        let mut words = command.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (None, _, _) => (),
+            (Some("step" | "s"), count, None) => match count.map_or(Ok(1), str::parse) {
+                Ok(count) if count > 0 => {
+                    let mut remaining: usize = count;
+                    self.resume(|_| {
+                        remaining -= 1;
+                        remaining == 0
+                    })?;
+                }
+                _ => eprintln!("error: invalid count `{}`", count.unwrap_or_default()),
+            },
+            (Some("continue" | "c"), None, None) => {
+                let breakpoints = self.breakpoints.clone();
+                self.resume(|next| breakpoints.contains(&next))?;
+            }
+            (Some("finish" | "f"), None, None) => match self.loop_exit() {
+                Some(exit) => {
+                    let breakpoints = self.breakpoints.clone();
+                    self.resume(|next| next == exit || breakpoints.contains(&next))?;
+                }
+                None => eprintln!("error: the next instruction is not inside a loop"),
+            },
+            (Some("break" | "b"), Some(location), None) => match self.find(location) {
+                Some(index) => {
+                    self.breakpoints.insert(index);
+                    println!(
+                        "breakpoint at {}:{}",
+                        self.source.name,
+                        self.program.positions()[index]
+                    );
+                }
+                None => eprintln!("error: no instruction at `{location}`"),
+            },
+            (Some("delete" | "d"), Some(location), None) => {
+                match self.find(location).filter(|i| self.breakpoints.remove(i)) {
+                    Some(index) => {
+                        println!(
+                            "removed breakpoint at {}:{}",
+                            self.source.name,
+                            self.program.positions()[index]
+                        )
+                    }
+                    None => eprintln!("error: no breakpoint at `{location}`"),
+                }
+            }
+            (Some("breakpoints"), None, None) => {
+                if self.breakpoints.is_empty() {
+                    println!("no breakpoints");
+                }
+                for index in &self.breakpoints {
+                    println!("{}:{}", self.source.name, self.program.positions()[*index]);
+                }
+            }
+            (Some("where" | "w"), None, None) => self.show_state(),
+            (Some("tape"), None, None) => print_tape(&self.machine, None),
+            (Some("tape"), Some(from), Some(to)) => print_tape(&self.machine, Some((from, to))),
+            (Some("pointer"), None, None) => print_pointer(&self.machine),
+            (Some("pointer"), Some(cell), None) => match self.cell(cell) {
+                Some(index) => self.machine.set_data_pointer(index),
+                None => eprintln!("error: invalid cell `{cell}`"),
+            },
+            (Some("set"), Some(cell), Some(value)) => match (self.cell(cell), value.parse()) {
+                (Some(index), Ok(value)) => self.machine.tape_mut()[index] = value,
+                (None, _) => eprintln!("error: invalid cell `{cell}`"),
+                (_, Err(_)) => {
+                    eprintln!("error: invalid value `{value}` for a {}-bit cell", C::BITS)
+                }
+            },
+            (Some("restart"), None, None) => {
+                self.machine = Machine::with_config(self.config.clone());
+                self.instruction_pointer = 0;
+                self.state = State::Paused;
+                self.show_state();
+            }
+            (Some("help"), None, None) => println!("{HELP}"),
+            (Some("quit" | "exit" | "q"), None, None) => return Ok(false),
+            _ => eprintln!("error: unknown command `{command}`, enter `help` to list commands"),
+        }
+
+        Ok(true)
+    }
+
+    /// Runs the program until `pause` returns `true` for the index of the next instruction
+    fn resume(&mut self, pause: impl FnMut(usize) -> bool) -> Result<(), Error> {
+        if self.state == State::Finished {
+            eprintln!("error: the program has finished, enter `restart` to run it again");
+            return Ok(());
+        }
+
+        let mut output = LastByte {
+            inner: io::stdout().lock(),
+            last: None,
+        };
+        let result = self.machine.run_until(
+            &self.program,
+            &mut self.instruction_pointer,
+            &mut self.input,
+            &mut output,
+            pause,
+        );
+
+        if output.last.is_some_and(|byte| byte != b'\n') {
+            println!();
+        }
+
+        match result.map_err(|error| Error::runtime(error, &self.source)) {
+            Ok(Status::Paused) => {
+                self.state = State::Paused;
+                self.show_state();
+            }
+            Ok(Status::Finished) => {
+                self.state = State::Finished;
+                println!("program finished");
+            }
+            Err(Error::Runtime(diagnostic)) => {
+                self.state = State::Failed;
+                eprint!("{diagnostic}");
+            }
+            Err(error) => return Err(error),
+        }
+
+        Ok(())
+    }
+
+    /// Shows the next instruction in the source code
+    fn show_state(&self) {
+        match self.program.positions().get(self.instruction_pointer) {
+            Some(position) if self.state != State::Finished => {
+                let label = match self.state {
+                    State::Failed => "failed here",
+                    _ => "paused here",
+                };
+                print!(
+                    "{}",
+                    snippet(label, *position, &self.source.name, &self.source.code)
+                );
+            }
+            _ => println!("program finished"),
+        }
+    }
+
+    /// The index right behind the innermost loop around the next instruction
+    fn loop_exit(&self) -> Option<usize> {
+        let next = self.instruction_pointer;
+
+        self.program
+            .bytecode()
+            .iter()
+            .take(next + 1)
+            .rev()
+            .find_map(|code| match *code {
+                Bytecode::JumpIfZero(exit) if exit > next => Some(exit),
+                _ => None,
+            })
+    }
+
+    /// Finds the instruction at a location of the form `<line>` or `<line>:<column>`
+    fn find(&self, location: &str) -> Option<usize> {
+        let (line, column) = match location.split_once(':') {
+            Some((line, column)) => (line.parse().ok()?, Some(column.parse().ok()?)),
+            None => (location.parse().ok()?, None),
+        };
+
+        self.program.positions().iter().position(
+            |&Position {
+                 line: l, column: c, ..
+             }| { l == line && column.is_none_or(|column| c == column) },
+        )
+    }
+
+    /// Parses the index of a cell on the tape
+    fn cell(&self, cell: &str) -> Option<usize> {
+        cell.parse()
+            .ok()
+            .filter(|&index| index < self.machine.tape().len())
+    }
+}
@@ -0,0 +1,10 @@
+use bfi::Extensions;
+
+use super::{print, Error, Source};
+
+/// Prints a program formatted to stdout
+pub fn fmt(source: Source, extensions: Extensions) -> Result<(), Error> {
+    let program = source.load()?.parse(extensions)?;
+
+    print(&bfi::format(program.instructions()))
+}
@@ -0,0 +1,212 @@
+pub mod args;
+pub mod check;
+pub mod compile;
+pub mod debug;
+pub mod fmt;
+pub mod repl;
+pub mod run;
+
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, Read, Write},
+    process::ExitCode,
+};
+
+use bfi::{
+    machine::DUMP_WINDOW, Cell, Config, Extensions, Machine, ParseError, Program, RuntimeError,
+    RuntimeErrorKind,
+};
+
+use self::args::Command;
+
+/// An error that ends the command line program, each kind reported with its own exit code
+pub enum Error {
+    /// The command line arguments are invalid
+    Usage(String),
+    /// The program could not be parsed, with the rendered diagnostic
+    Parse(String),
+    /// The program failed while running, with the rendered diagnostic
+    Runtime(String),
+    /// Reading or writing a file, input or output failed
+    Io(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::Parse(_) => ExitCode::from(1),
+            Error::Usage(_) => ExitCode::from(2),
+            Error::Runtime(_) => ExitCode::from(3),
+            Error::Io(_) => ExitCode::from(4),
+        }
+    }
+
+    /// Reports the error on stderr
+    pub fn report(&self) {
+        match self {
+            Error::Usage(message) => {
+                eprintln!("error: {message}");
+                eprintln!("\nfor more information, try `bfi --help`");
+            }
+            Error::Parse(diagnostic) | Error::Runtime(diagnostic) => eprint!("{diagnostic}"),
+            Error::Io(message) => eprintln!("error: {message}"),
+        }
+    }
+
+    pub fn io(context: impl Display, error: io::Error) -> Self {
+        Error::Io(format!("{context}: {error}"))
+    }
+
+    pub fn parse(error: ParseError, source: &LoadedSource) -> Self {
+        Error::Parse(error.render(&source.name, &source.code))
+    }
+
+    pub fn runtime(error: RuntimeError, source: &LoadedSource) -> Self {
+        match error.kind {
+            RuntimeErrorKind::Input(_)
+            | RuntimeErrorKind::Output(_)
+            | RuntimeErrorKind::Trace(_) => Error::Io(error.to_string()),
+            _ => Error::Runtime(error.render(&source.name, &source.code)),
+        }
+    }
+}
+
+/// Where the source code of the program comes from
+pub enum Source {
+    File(String),
+    Inline(String),
+    Stdin,
+}
+
+/// The source code of a program, together with the name it is referred to by in diagnostics
+pub struct LoadedSource {
+    pub name: String,
+    pub code: String,
+    /// Input that followed the program on stdin after a `!`, which replaces reading from stdin
+    pub stdin_input: Option<Vec<u8>>,
+}
+
+impl Source {
+    pub fn load(self) -> Result<LoadedSource, Error> {
+        match self {
+            Source::File(path) => {
+                let code = fs::read_to_string(&path).map_err(|e| {
+                    Error::io(format_args!("failed to read program file `{path}`"), e)
+                })?;
+
+                Ok(LoadedSource {
+                    name: path,
+                    code,
+                    stdin_input: None,
+                })
+            }
+            Source::Inline(code) => Ok(LoadedSource {
+                name: "<inline>".to_string(),
+                code,
+                stdin_input: None,
+            }),
+            Source::Stdin => {
+                let mut bytes = vec![];
+                io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| Error::io("failed to read program from stdin", e))?;
+
+                let split = bytes.iter().position(|&b| b == b'!');
+                let code = &bytes[..split.unwrap_or(bytes.len())];
+
+                Ok(LoadedSource {
+                    name: "<stdin>".to_string(),
+                    code: String::from_utf8_lossy(code).into_owned(),
+                    stdin_input: Some(split.map_or(vec![], |i| bytes[i + 1..].to_vec())),
+                })
+            }
+        }
+    }
+}
+
+impl LoadedSource {
+    pub fn parse(&self, extensions: Extensions) -> Result<Program, Error> {
+        self.parse_with_config(extensions, &Config::default())
+    }
+
+    /// Parses the program optimized for machines with a configuration
+    pub fn parse_with_config(
+        &self,
+        extensions: Extensions,
+        config: &Config,
+    ) -> Result<Program, Error> {
+        Program::parse_with_config(&self.code, extensions, config)
+            .map_err(|error| Error::parse(error, self))
+    }
+}
+
+/// Remembers the last byte written, so the prompt can be moved to a new line after output
+pub struct LastByte<W: Write> {
+    pub inner: W,
+    pub last: Option<u8>,
+}
+
+impl<W: Write> Write for LastByte<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if written > 0 {
+            self.last = Some(buf[written - 1]);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes text to stdout, failing instead of panicking if stdout is closed
+pub fn print(text: &str) -> Result<(), Error> {
+    io::stdout()
+        .lock()
+        .write_all(text.as_bytes())
+        .map_err(|e| Error::io("failed to write output", e))
+}
+
+/// Prints the cells from `from` to `to` inclusive for `tape <from> <to>`, or the cells around the
+/// data pointer for `tape` without a range
+pub fn print_tape<C: Cell>(machine: &Machine<C>, range: Option<(&str, &str)>) {
+    let Some((from, to)) = range else {
+        let pointer = machine.data_pointer();
+        let start = pointer.saturating_sub(DUMP_WINDOW);
+        println!("{}", machine.dump(start..pointer + DUMP_WINDOW + 1));
+        return;
+    };
+
+    match (from.parse(), to.parse::<usize>()) {
+        (Ok(from), Ok(to)) if from <= to => {
+            println!("{}", machine.dump(from..to.saturating_add(1)))
+        }
+        _ => eprintln!("error: invalid range `{from} {to}`"),
+    }
+}
+
+/// Prints the data pointer and the value of the current cell for `pointer`
+pub fn print_pointer<C: Cell>(machine: &Machine<C>) {
+    let pointer = machine.data_pointer();
+    println!(
+        "data pointer at {pointer}, current cell is {}",
+        machine.tape()[pointer]
+    );
+}
+
+/// Parses the command line arguments and executes the command
+pub fn main(args: impl Iterator<Item = String>) -> Result<(), Error> {
+    match args::parse(args)? {
+        Command::Help(help) => print(&format!("{help}\n")),
+        Command::Version => print(&format!("bfi {}\n", env!("CARGO_PKG_VERSION"))),
+        Command::Run(options) => run::run(options),
+        Command::Check(source) => check::check(source),
+        Command::Fmt(source, extensions) => fmt::fmt(source, extensions),
+        Command::Repl(options, extensions) => repl::repl(options, extensions),
+        Command::Debug(options) => debug::debug(options),
+        Command::Compile(options) => compile::compile(options),
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use bfi::{Cell, Config, Extensions, Machine, Program};
+
+use super::{args::MachineOptions, print_pointer, print_tape, Error, LastByte, LoadedSource};
+
+const HELP: &str = "Every line that does not start with `:` is run as code on the same tape.
+
+commands:
+    :tape [<from> <to>]  show the cells around the data pointer or in a range
+    :pointer             show the data pointer and the value of the current cell
+    :reset               clear the tape and move the data pointer back to the start
+    :load <file>         run a file on the current tape
+    :help                show this help
+    :quit                exit the repl";
+
+/// Runs code interactively, line by line on the same machine
+pub fn repl(options: MachineOptions, extensions: Extensions) -> Result<(), Error> {
+    match options.cell_size {
+        8 => Repl::<u8>::new(options.config, extensions).run(),
+        16 => Repl::<u16>::new(options.config, extensions).run(),
+        32 => Repl::<u32>::new(options.config, extensions).run(),
+        _ => Repl::<u64>::new(options.config, extensions).run(),
+    }
+}
+
+struct Repl<C: Cell> {
+    config: Config,
+    extensions: Extensions,
+    machine: Machine<C>,
+}
+
+impl<C: Cell> Repl<C> {
+    fn new(config: Config, extensions: Extensions) -> Self {
+        Self {
+            machine: Machine::with_config(config.clone()),
+            config,
+            extensions,
+        }
+    }
+
+    fn run(&mut self) -> Result<(), Error> {
+        let mut line = String::new();
+
+        loop {
+            print!("bfi> ");
+            io::stdout()
+                .flush()
+                .map_err(|e| Error::io("failed to write output", e))?;
+
+            line.clear();
+            let read = io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|e| Error::io("failed to read stdin", e))?;
+
+            // End of input quits, like `:quit`
+            if read == 0 {
+                println!();
+                return Ok(());
+            }
+
+            let line = line.trim();
+            match line.strip_prefix(':') {
+                Some(command) => {
+                    if !self.command(command)? {
+                        return Ok(());
+                    }
+                }
+                None => self.execute(LoadedSource {
+                    name: "<repl>".to_string(),
+                    code: line.to_string(),
+                    stdin_input: None,
+                })?,
+            }
+        }
+    }
+
+    /// Executes a command, returning `false` if the repl should exit
+    fn command(&mut self, command: &str) -> Result<bool, Error> {
+        let mut words = command.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (Some("tape"), None, None) => print_tape(&self.machine, None),
+            (Some("tape"), Some(from), Some(to)) => print_tape(&self.machine, Some((from, to))),
+            (Some("pointer"), None, None) => print_pointer(&self.machine),
+            (Some("reset"), None, None) => self.machine = Machine::with_config(self.config.clone()),
+            (Some("load"), Some(path), None) => match fs::read_to_string(path) {
+                Ok(code) => self.execute(LoadedSource {
+                    name: path.to_string(),
+                    code,
+                    stdin_input: None,
+                })?,
+                Err(error) => eprintln!("error: failed to read program file `{path}`: {error}"),
+            },
+            (Some("help"), None, None) => println!("{HELP}"),
+            (Some("quit" | "exit"), None, None) => return Ok(false),
+            _ => eprintln!("error: unknown command `:{command}`, enter `:help` to list commands"),
+        }
+
+        Ok(true)
+    }
+
+    /// Runs code on the machine, reporting errors in the code without leaving the repl
+    fn execute(&mut self, source: LoadedSource) -> Result<(), Error> {
+        let parsed = Program::parse_with_config(&source.code, self.extensions, &self.config);
+        let program = match parsed {
+            Ok(program) => program,
+            Err(error) => {
+                eprint!("{}", error.render(&source.name, &source.code));
+                return Ok(());
+            }
+        };
+
+        let mut output = LastByte {
+            inner: io::stdout().lock(),
+            last: None,
+        };
+        let result = self
+            .machine
+            .run_with(&program, io::stdin().lock(), &mut output);
+
+        if output.last.is_some_and(|byte| byte != b'\n') {
+            println!();
+        }
+
+        match result.map_err(|error| Error::runtime(error, &source)) {
+            Err(Error::Runtime(diagnostic)) => eprint!("{diagnostic}"),
+            result => result?,
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+};
+
+use bfi::{Cell, Config, Extensions, Machine, Profile, Program, RuntimeError};
+
+use super::{args::MachineOptions, Error, Source};
+
+/// A source of input for the program, given on the command line
+pub enum Input {
+    Text(Vec<u8>),
+    File(String),
+}
+
+pub struct RunOptions {
+    pub source: Source,
+    pub machine: MachineOptions,
+    pub extensions: Extensions,
+    /// The file every executed instruction is traced to
+    pub trace: Option<String>,
+    /// Whether a profile of the run is reported on stderr
+    pub profile: bool,
+    pub inputs: Vec<Input>,
+    /// Whether stdin is read after the given inputs
+    pub stdin: bool,
+}
+
+/// Reads the inputs given on the command line one after another
+pub fn chain_inputs(inputs: Vec<Input>) -> Result<Box<dyn Read>, Error> {
+    let mut input: Box<dyn Read> = Box::new(io::empty());
+    for given in inputs {
+        let bytes = match given {
+            Input::Text(bytes) => bytes,
+            Input::File(path) => fs::read(&path)
+                .map_err(|e| Error::io(format_args!("failed to read input file `{path}`"), e))?,
+        };
+        input = Box::new(input.chain(io::Cursor::new(bytes)));
+    }
+
+    Ok(input)
+}
+
+/// Runs a program on stdout, reading from the inputs given on the command line
+pub fn run(options: RunOptions) -> Result<(), Error> {
+    let source = options.source.load()?;
+    let mut program = source.parse_with_config(options.extensions, &options.machine.config)?;
+
+    // Every single instruction is traced or profiled, not the operations they are optimized into
+    let mut recording = match (&options.trace, options.profile) {
+        (Some(path), _) => {
+            program = program.unoptimized();
+            let file = File::create(path)
+                .map_err(|e| Error::io(format_args!("failed to create trace file `{path}`"), e))?;
+            Recording::Trace(file)
+        }
+        (None, true) => {
+            program = program.unoptimized();
+            Recording::Profile(Profile::new(&program))
+        }
+        (None, false) => Recording::Nothing,
+    };
+
+    let mut input = chain_inputs(options.inputs)?;
+    if options.stdin {
+        input = match source.stdin_input.clone() {
+            Some(bytes) => Box::new(input.chain(io::Cursor::new(bytes))),
+            None => Box::new(input.chain(io::stdin().lock())),
+        };
+    }
+
+    let output = io::stdout().lock();
+    let config = options.machine.config;
+    let result = match options.machine.cell_size {
+        8 => execute::<u8>(config, &program, input, output, &mut recording),
+        16 => execute::<u16>(config, &program, input, output, &mut recording),
+        32 => execute::<u32>(config, &program, input, output, &mut recording),
+        _ => execute::<u64>(config, &program, input, output, &mut recording),
+    };
+
+    // The profile is reported even if the program failed, since it shows how it got there
+    if let Recording::Profile(profile) = &recording {
+        eprint!("{}", profile.report(&program, &source.name, &source.code));
+    }
+
+    result.map_err(|error| Error::runtime(error, &source))
+}
+
+/// What is recorded about a run besides its output
+enum Recording {
+    Nothing,
+    Trace(File),
+    Profile(Profile),
+}
+
+/// Runs a program on a new machine, recording it as requested
+fn execute<C: Cell>(
+    config: Config,
+    program: &Program,
+    input: impl Read,
+    output: impl Write,
+    recording: &mut Recording,
+) -> Result<(), RuntimeError> {
+    let mut machine = Machine::<C>::with_config(config);
+
+    match recording {
+        #[cfg(feature = "jit")]
+        Recording::Nothing => machine.run_jit(program, input, output),
+        #[cfg(not(feature = "jit"))]
+        Recording::Nothing => machine.run_with(program, input, output),
+        Recording::Trace(trace) => machine.run_traced(program, input, output, trace),
+        Recording::Profile(profile) => machine.run_profiled(program, input, output, profile),
+    }
+}
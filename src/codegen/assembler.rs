@@ -0,0 +1,507 @@
+//! A minimal assembler for the subset of x86-64 the native backends use
+
+/// A general purpose register, numbered like in the encoding of instructions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)] // Not every register is used, but all of them can be encoded
+pub(crate) enum Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Reg {
+    fn low(self) -> u8 {
+        self as u8 & 7
+    }
+
+    fn high(self) -> u8 {
+        self as u8 >> 3
+    }
+}
+
+/// The size of the operands of an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Size {
+    Byte,
+    Word,
+    Dword,
+    Qword,
+}
+
+impl Size {
+    /// The size of a cell with a width in bits
+    pub fn of_bits(bits: u32) -> Self {
+        match bits {
+            8 => Size::Byte,
+            16 => Size::Word,
+            32 => Size::Dword,
+            _ => Size::Qword,
+        }
+    }
+
+    /// The size in bytes
+    pub fn bytes(self) -> u8 {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+            Size::Dword => 4,
+            Size::Qword => 8,
+        }
+    }
+}
+
+/// A memory operand
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Mem {
+    /// `[base + index * scale + disp]`, where the scale is 1, 2, 4 or 8
+    Sib {
+        base: Reg,
+        index: Option<Reg>,
+        scale: u8,
+        disp: i32,
+    },
+    /// `[rip + label]`
+    Rip(Label),
+}
+
+impl Mem {
+    /// `[base + disp]`
+    pub fn base(base: Reg, disp: i32) -> Self {
+        Mem::Sib {
+            base,
+            index: None,
+            scale: 1,
+            disp,
+        }
+    }
+
+    /// `[base + index * scale]`
+    pub fn indexed(base: Reg, index: Reg, scale: u8) -> Self {
+        Mem::Sib {
+            base,
+            index: Some(index),
+            scale,
+            disp: 0,
+        }
+    }
+}
+
+/// The register or memory operand encoded in the ModRM byte
+#[derive(Clone, Copy)]
+enum Rm {
+    Reg(Reg),
+    Mem(Mem),
+}
+
+/// Arithmetic and logic instructions that share their encoding
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Alu {
+    Add,
+    Sub,
+    Xor,
+    Cmp,
+}
+
+impl Alu {
+    /// The opcode extension of the forms with an immediate
+    fn extension(self) -> u8 {
+        match self {
+            Alu::Add => 0,
+            Alu::Sub => 5,
+            Alu::Xor => 6,
+            Alu::Cmp => 7,
+        }
+    }
+
+    /// The opcode of the form `op r/m, r` for operands larger than a byte
+    fn opcode(self) -> u8 {
+        self.extension() * 8 + 1
+    }
+}
+
+/// A condition of a conditional jump
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)] // Not every condition is used, but all of them can be encoded
+pub(crate) enum Cond {
+    Overflow = 0x0,
+    NoOverflow = 0x1,
+    Below = 0x2,
+    AboveOrEqual = 0x3,
+    Equal = 0x4,
+    NotEqual = 0x5,
+    BelowOrEqual = 0x6,
+    Above = 0x7,
+    Sign = 0x8,
+    NotSign = 0x9,
+    Less = 0xc,
+    GreaterOrEqual = 0xd,
+    LessOrEqual = 0xe,
+    Greater = 0xf,
+}
+
+/// A position in the code that is bound once it is known
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Label(usize);
+
+/// A 32-bit displacement that points at a label, relative to the end of its instruction
+struct Fixup {
+    at: usize,
+    end: usize,
+    label: Label,
+}
+
+/// Encodes instructions into machine code, resolving jumps to labels when finished
+#[derive(Default)]
+pub(crate) struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<i64>>,
+    fixups: Vec<Fixup>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The amount of bytes of code so far
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Binds a label to the current position
+    pub fn bind(&mut self, label: Label) {
+        self.bind_at(label, self.code.len() as i64);
+    }
+
+    /// Binds a label to a position relative to the start of the code, which may lie outside of it
+    pub fn bind_at(&mut self, label: Label, position: i64) {
+        debug_assert!(self.labels[label.0].is_none(), "label bound twice");
+        self.labels[label.0] = Some(position);
+    }
+
+    /// Appends raw bytes, like data or instructions without operands
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// Resolves all labels, returning the machine code
+    ///
+    /// Panics if a label that is used was never bound.
+    pub fn finish(mut self) -> Vec<u8> {
+        for Fixup { at, end, label } in self.fixups {
+            let target = self.labels[label.0].expect("label is used but never bound");
+            let displacement = i32::try_from(target - end as i64).expect("label is out of range");
+            self.code[at..at + 4].copy_from_slice(&displacement.to_le_bytes());
+        }
+
+        self.code
+    }
+
+    /// Encodes an instruction with a ModRM operand, where `reg` is either a register or an opcode
+    /// extension
+    fn encode(&mut self, size: Size, opcode: &[u8], reg: u8, rm: Rm, immediate: &[u8]) {
+        if size == Size::Word {
+            self.code.push(0x66);
+        }
+
+        let (x, b) = match rm {
+            Rm::Reg(rm) => (0, rm.high()),
+            Rm::Mem(Mem::Sib { base, index, .. }) => (index.map_or(0, Reg::high), base.high()),
+            Rm::Mem(Mem::Rip(_)) => (0, 0),
+        };
+        let w = u8::from(size == Size::Qword);
+        let rex = 0x40 | w << 3 | (reg >> 3) << 2 | x << 1 | b;
+        // Byte registers above `bl` need a prefix to not be read as `ah` to `bh`
+        if rex != 0x40 || size == Size::Byte {
+            self.code.push(rex);
+        }
+
+        self.code.extend_from_slice(opcode);
+        let reg = (reg & 7) << 3;
+
+        match rm {
+            Rm::Reg(rm) => self.code.push(0xc0 | reg | rm.low()),
+            Rm::Mem(Mem::Sib {
+                base,
+                index,
+                scale,
+                disp,
+            }) => {
+                // `rbp` and `r13` as a base without a displacement would mean no base at all
+                let mode = match disp {
+                    0 if base.low() != 5 => 0x00,
+                    -128..=127 => 0x40,
+                    _ => 0x80,
+                };
+                // An index of `rsp` means no index at all
+                let index = index.map_or(4, Reg::low);
+                self.code.push(mode | reg | 4);
+                self.code
+                    .push((scale.trailing_zeros() as u8) << 6 | index << 3 | base.low());
+
+                match mode {
+                    0x00 => (),
+                    0x40 => self.code.push(disp as u8),
+                    _ => self.code.extend_from_slice(&disp.to_le_bytes()),
+                }
+            }
+            Rm::Mem(Mem::Rip(label)) => {
+                self.code.push(reg | 5);
+                let at = self.code.len();
+                self.code.extend_from_slice(&[0; 4]);
+                self.fixups.push(Fixup {
+                    at,
+                    end: at + 4 + immediate.len(),
+                    label,
+                });
+            }
+        }
+
+        self.code.extend_from_slice(immediate);
+    }
+
+    /// Emits a 32-bit displacement to a label that ends its instruction
+    fn relative(&mut self, label: Label) {
+        let at = self.code.len();
+        self.code.extend_from_slice(&[0; 4]);
+        self.fixups.push(Fixup {
+            at,
+            end: at + 4,
+            label,
+        });
+    }
+
+    /// `mov dst, src`
+    pub fn mov(&mut self, dst: Reg, src: Reg) {
+        self.encode(Size::Qword, &[0x89], src as u8, Rm::Reg(dst), &[]);
+    }
+
+    /// `mov dst, imm`, using the shortest encoding
+    pub fn mov_imm(&mut self, dst: Reg, imm: i64) {
+        if let Ok(imm) = u32::try_from(imm) {
+            // Writing the lower half zero extends into the whole register
+            if dst.high() != 0 {
+                self.code.push(0x41);
+            }
+            self.code.push(0xb8 + dst.low());
+            self.code.extend_from_slice(&imm.to_le_bytes());
+        } else if let Ok(imm) = i32::try_from(imm) {
+            self.encode(Size::Qword, &[0xc7], 0, Rm::Reg(dst), &imm.to_le_bytes());
+        } else {
+            self.code.push(0x48 | dst.high());
+            self.code.push(0xb8 + dst.low());
+            self.code.extend_from_slice(&imm.to_le_bytes());
+        }
+    }
+
+    /// `op dst, src`
+    pub fn alu(&mut self, op: Alu, dst: Reg, src: Reg) {
+        self.encode(Size::Qword, &[op.opcode()], src as u8, Rm::Reg(dst), &[]);
+    }
+
+    /// `op dst, imm`
+    pub fn alu_imm(&mut self, op: Alu, dst: Reg, imm: i32) {
+        match i8::try_from(imm) {
+            Ok(imm) => self.encode(
+                Size::Qword,
+                &[0x83],
+                op.extension(),
+                Rm::Reg(dst),
+                &[imm as u8],
+            ),
+            Err(_) => self.encode(
+                Size::Qword,
+                &[0x81],
+                op.extension(),
+                Rm::Reg(dst),
+                &imm.to_le_bytes(),
+            ),
+        }
+    }
+
+    /// `op size [mem], imm`, where the immediate is truncated to the size, or sign extended from 32
+    /// bits for quadwords
+    pub fn alu_mem_imm(&mut self, op: Alu, size: Size, mem: Mem, imm: i64) {
+        let extension = op.extension();
+
+        match size {
+            Size::Byte => self.encode(size, &[0x80], extension, Rm::Mem(mem), &[imm as u8]),
+            _ if i8::try_from(imm).is_ok() => {
+                self.encode(size, &[0x83], extension, Rm::Mem(mem), &[imm as u8])
+            }
+            Size::Word => self.encode(
+                size,
+                &[0x81],
+                extension,
+                Rm::Mem(mem),
+                &(imm as u16).to_le_bytes(),
+            ),
+            _ => self.encode(
+                size,
+                &[0x81],
+                extension,
+                Rm::Mem(mem),
+                &(imm as u32).to_le_bytes(),
+            ),
+        }
+    }
+
+    /// `op size [mem], src`
+    pub fn alu_mem(&mut self, op: Alu, size: Size, mem: Mem, src: Reg) {
+        let opcode = match size {
+            Size::Byte => op.opcode() - 1,
+            _ => op.opcode(),
+        };
+        self.encode(size, &[opcode], src as u8, Rm::Mem(mem), &[]);
+    }
+
+    /// `test dst, src`
+    pub fn test(&mut self, dst: Reg, src: Reg) {
+        self.encode(Size::Qword, &[0x85], src as u8, Rm::Reg(dst), &[]);
+    }
+
+    /// `lea dst, [mem]`
+    pub fn lea(&mut self, dst: Reg, mem: Mem) {
+        self.encode(Size::Qword, &[0x8d], dst as u8, Rm::Mem(mem), &[]);
+    }
+
+    /// Loads a value of a size from memory, zero extending it to the whole register
+    pub fn load(&mut self, size: Size, dst: Reg, mem: Mem) {
+        match size {
+            Size::Byte => self.encode(Size::Dword, &[0x0f, 0xb6], dst as u8, Rm::Mem(mem), &[]),
+            Size::Word => self.encode(Size::Dword, &[0x0f, 0xb7], dst as u8, Rm::Mem(mem), &[]),
+            _ => self.encode(size, &[0x8b], dst as u8, Rm::Mem(mem), &[]),
+        }
+    }
+
+    /// Stores the lower part of a register of a size to memory
+    pub fn store(&mut self, size: Size, mem: Mem, src: Reg) {
+        let opcode = match size {
+            Size::Byte => 0x88,
+            _ => 0x89,
+        };
+        self.encode(size, &[opcode], src as u8, Rm::Mem(mem), &[]);
+    }
+
+    /// `mov size [mem], imm`, where the immediate is truncated to the size, or sign extended from 32
+    /// bits for quadwords
+    pub fn store_imm(&mut self, size: Size, mem: Mem, imm: i64) {
+        match size {
+            Size::Byte => self.encode(size, &[0xc6], 0, Rm::Mem(mem), &[imm as u8]),
+            Size::Word => self.encode(size, &[0xc7], 0, Rm::Mem(mem), &(imm as u16).to_le_bytes()),
+            _ => self.encode(size, &[0xc7], 0, Rm::Mem(mem), &(imm as u32).to_le_bytes()),
+        }
+    }
+
+    /// `imul dst, src, imm`
+    pub fn imul_imm(&mut self, dst: Reg, src: Reg, imm: i32) {
+        self.encode(
+            Size::Qword,
+            &[0x69],
+            dst as u8,
+            Rm::Reg(src),
+            &imm.to_le_bytes(),
+        );
+    }
+
+    /// `imul dst, src`
+    pub fn imul(&mut self, dst: Reg, src: Reg) {
+        self.encode(Size::Qword, &[0x0f, 0xaf], dst as u8, Rm::Reg(src), &[]);
+    }
+
+    /// `mul src`, multiplying `rax` into `rdx:rax`
+    pub fn mul(&mut self, src: Reg) {
+        self.encode(Size::Qword, &[0xf7], 4, Rm::Reg(src), &[]);
+    }
+
+    /// `idiv src`, dividing `rdx:rax` into `rax` with the remainder in `rdx`
+    pub fn idiv(&mut self, src: Reg) {
+        self.encode(Size::Qword, &[0xf7], 7, Rm::Reg(src), &[]);
+    }
+
+    /// `cqo`, sign extending `rax` into `rdx`
+    pub fn cqo(&mut self) {
+        self.code.extend_from_slice(&[0x48, 0x99]);
+    }
+
+    /// `neg dst`
+    pub fn neg(&mut self, dst: Reg) {
+        self.encode(Size::Qword, &[0xf7], 3, Rm::Reg(dst), &[]);
+    }
+
+    /// `shl dst, count`
+    pub fn shl(&mut self, dst: Reg, count: u8) {
+        self.encode(Size::Qword, &[0xc1], 4, Rm::Reg(dst), &[count]);
+    }
+
+    /// `shr dst, count`
+    pub fn shr(&mut self, dst: Reg, count: u8) {
+        self.encode(Size::Qword, &[0xc1], 5, Rm::Reg(dst), &[count]);
+    }
+
+    /// `cmovb dst, src`
+    pub fn cmov_below(&mut self, dst: Reg, src: Reg) {
+        self.encode(Size::Qword, &[0x0f, 0x42], dst as u8, Rm::Reg(src), &[]);
+    }
+
+    pub fn push(&mut self, src: Reg) {
+        if src.high() != 0 {
+            self.code.push(0x41);
+        }
+        self.code.push(0x50 + src.low());
+    }
+
+    pub fn pop(&mut self, dst: Reg) {
+        if dst.high() != 0 {
+            self.code.push(0x41);
+        }
+        self.code.push(0x58 + dst.low());
+    }
+
+    pub fn jmp(&mut self, label: Label) {
+        self.code.push(0xe9);
+        self.relative(label);
+    }
+
+    pub fn jump_if(&mut self, cond: Cond, label: Label) {
+        self.code.extend_from_slice(&[0x0f, 0x80 + cond as u8]);
+        self.relative(label);
+    }
+
+    pub fn call(&mut self, label: Label) {
+        self.code.push(0xe8);
+        self.relative(label);
+    }
+
+    /// `call target`
+    #[cfg(feature = "jit")]
+    pub fn call_reg(&mut self, target: Reg) {
+        self.encode(Size::Dword, &[0xff], 2, Rm::Reg(target), &[]);
+    }
+
+    pub fn ret(&mut self) {
+        self.code.push(0xc3);
+    }
+
+    pub fn syscall(&mut self) {
+        self.code.extend_from_slice(&[0x0f, 0x05]);
+    }
+}
@@ -0,0 +1,448 @@
+//! Transpiles brainfuck to a standalone C program
+
+use std::{fmt::Write, mem};
+
+use super::Unfolded;
+use crate::{
+    cell::Cell,
+    config::{Boundary, Config, Eof, Overflow},
+    lexer::Position,
+    optimizer::Op,
+    program::Program,
+};
+
+/// The indentation of each level of nested blocks
+const INDENT: &str = "    ";
+
+/// Which helper functions the generated program calls
+#[derive(Default)]
+struct Uses {
+    moves: bool,
+    add: bool,
+    mul_add: bool,
+    output: bool,
+    input: bool,
+    dump: bool,
+}
+
+/// Transpiles a program to a C program for cells of type `C`, optimized for the configuration
+///
+/// The program only depends on the C standard library. Like `bfi`, it reports errors on stderr
+/// and exits with code 3 if the program fails while running, or with code 4 if writing the output
+/// fails.
+///
+/// # Panics
+///
+/// Panics if the data pointer starts at the largest index, which no tape can hold.
+pub fn transpile<C: Cell>(program: &Program, config: &Config) -> String {
+    let optimized = program.optimized_for(config);
+    let unfolded = Unfolded::new(program, config);
+    let mut uses = Uses::default();
+    let mut replays = vec![];
+    let mut body = String::new();
+    write_ops(
+        optimized.ops(),
+        config,
+        &unfolded,
+        &mut uses,
+        &mut replays,
+        &mut body,
+    );
+
+    // A failed operation that was optimized from several instructions runs them one by one
+    let mut functions = String::new();
+    for (index, ops) in replays.iter().enumerate() {
+        let mut statements = String::new();
+        write_ops(
+            ops,
+            config,
+            &unfolded,
+            &mut uses,
+            &mut vec![],
+            &mut statements,
+        );
+        let _ = write!(
+            functions,
+            "\nstatic void replay_{index}(void) {{\n{statements}}}\n"
+        );
+    }
+
+    let size = config
+        .start
+        .checked_add(1)
+        .expect("data pointer starts past the largest tape")
+        .max(config.tape_size);
+    let mut program = format!(
+        "/* Generated by bfi */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef uint{bits}_t cell;
+
+#define CELL_MAX UINT{bits}_MAX
+
+static cell *tape;
+static size_t size = {size};
+static size_t ptr = {start};
+",
+        bits = C::BITS,
+        start = config.start,
+    );
+
+    let fails = (uses.moves && config.boundary != Boundary::Wrap)
+        || ((uses.add || uses.mul_add) && config.overflow == Overflow::Trap);
+    if fails {
+        program.push_str(FAIL_FUNCTION);
+    }
+    if uses.moves {
+        program.push_str(offset_index_function(config.boundary));
+    }
+    if uses.add {
+        program.push_str(add_function(config.overflow));
+    }
+    if uses.mul_add {
+        program.push_str(&mul_add_function(config.overflow));
+    }
+    if uses.output {
+        program.push_str(OUTPUT_FUNCTION);
+    }
+    if uses.input {
+        program.push_str(&input_function(config.eof));
+    }
+    if uses.dump {
+        program.push_str(DUMP_FUNCTION);
+    }
+    program.push_str(&functions);
+
+    let _ = write!(
+        program,
+        "
+int main(void) {{
+    tape = calloc(size, sizeof(cell));
+    if (!tape) {{
+        fprintf(stderr, \"error: failed to allocate the tape\\n\");
+        return 3;
+    }}
+
+{body}
+    if (fflush(stdout) != 0) {{
+        fprintf(stderr, \"error: failed to write output\\n\");
+        return 4;
+    }}
+
+    return 0;
+}}
+"
+    );
+
+    program
+}
+
+/// Writes the statements of the operations, with an explicit stack of the loops they are nested in
+///
+/// The instructions of operations that can fail and were optimized from several are added to
+/// `replays`, and the operations pass the function that runs them to the helper that fails.
+fn write_ops<'a>(
+    ops: &[(Op, Position)],
+    config: &Config,
+    unfolded: &'a Unfolded,
+    uses: &mut Uses,
+    replays: &mut Vec<&'a [(Op, Position)]>,
+    output: &mut String,
+) {
+    let mut rest = ops.iter();
+
+    // The operations after each loop that is currently open
+    let mut stack = vec![];
+
+    loop {
+        let Some((op, position)) = rest.next() else {
+            let Some(outer) = stack.pop() else {
+                return;
+            };
+
+            rest = outer;
+            let _ = writeln!(output, "{}}}", INDENT.repeat(stack.len() + 1));
+            continue;
+        };
+
+        let indent = INDENT.repeat(stack.len() + 1);
+        let Position { line, column, .. } = *position;
+        let replay = match unfolded.get(op, *position) {
+            Some(instructions) => {
+                replays.push(instructions);
+                format!("replay_{}", replays.len() - 1)
+            }
+            None => "NULL".to_string(),
+        };
+
+        let _ = match *op {
+            Op::Add(amount) if config.overflow != Overflow::Wrap => {
+                uses.add = true;
+                writeln!(output, "{indent}add({amount}, {line}, {column}, {replay});")
+            }
+            // Unsigned arithmetic in C wraps around, so no checks are needed
+            Op::Add(amount) if amount < 0 => {
+                writeln!(output, "{indent}tape[ptr] -= {};", amount.unsigned_abs())
+            }
+            Op::Add(amount) => writeln!(output, "{indent}tape[ptr] += {amount};"),
+            Op::Move(offset) => {
+                uses.moves = true;
+                writeln!(
+                    output,
+                    "{indent}ptr = offset_index({offset}, {line}, {column}, {replay});"
+                )
+            }
+            Op::Output => {
+                uses.output = true;
+                writeln!(output, "{indent}output();")
+            }
+            Op::Input => {
+                uses.input = true;
+                writeln!(output, "{indent}input();")
+            }
+            Op::Dump => {
+                uses.dump = true;
+                writeln!(output, "{indent}dump();")
+            }
+            Op::Clear => writeln!(output, "{indent}tape[ptr] = 0;"),
+            Op::MulAdd { offset, factor } => {
+                uses.moves = true;
+                uses.mul_add = true;
+                writeln!(
+                    output,
+                    "{indent}mul_add({offset}, {factor}, {line}, {column}, {replay});"
+                )
+            }
+            Op::Scan(stride) => {
+                uses.moves = true;
+                let _ = writeln!(output, "{indent}while (tape[ptr]) {{");
+                let _ = writeln!(
+                    output,
+                    "{indent}{INDENT}ptr = offset_index({stride}, {line}, {column}, {replay});"
+                );
+                writeln!(output, "{indent}}}")
+            }
+            Op::Loop(ref body, _) => {
+                let _ = writeln!(output, "{indent}while (tape[ptr]) {{");
+                stack.push(mem::replace(&mut rest, body.iter()));
+                continue;
+            }
+        };
+    }
+}
+
+const FAIL_FUNCTION: &str = "
+static void fail(const char *message, int line, int column, void (*replay)(void)) {
+    if (replay) {
+        replay();
+    }
+    fflush(stdout);
+    fprintf(stderr, \"error: %s at %d:%d\\n\", message, line, column);
+    exit(3);
+}
+";
+
+fn offset_index_function(boundary: Boundary) -> &'static str {
+    match boundary {
+        Boundary::Error => {
+            "
+static size_t offset_index(long long offset, int line, int column, void (*replay)(void)) {
+    if (offset < 0 && (size_t)-offset > ptr) {
+        fail(\"data pointer moved past the start of the tape\", line, column, replay);
+    }
+    if (offset > 0 && (size_t)offset >= size - ptr) {
+        fail(\"data pointer moved past the end of the tape\", line, column, replay);
+    }
+    return ptr + offset;
+}
+"
+        }
+        Boundary::Wrap => {
+            "
+static size_t offset_index(long long offset, int line, int column, void (*replay)(void)) {
+    long long moved = ((long long)ptr + offset) % (long long)size;
+    (void)line;
+    (void)column;
+    (void)replay;
+    return (size_t)(moved < 0 ? moved + (long long)size : moved);
+}
+"
+        }
+        // Growing the tape at the start moves the data pointer along
+        Boundary::Grow => {
+            "
+static size_t offset_index(long long offset, int line, int column, void (*replay)(void)) {
+    if (offset > 0 && (size_t)offset >= size - ptr) {
+        size_t needed = ptr + offset + 1;
+        size_t grown = size;
+        while (grown < needed) {
+            grown *= 2;
+        }
+        tape = realloc(tape, grown * sizeof(cell));
+        if (!tape) {
+            fail(\"failed to grow the tape\", line, column, replay);
+        }
+        memset(tape + size, 0, (grown - size) * sizeof(cell));
+        size = grown;
+    } else if (offset < 0 && (size_t)-offset > ptr) {
+        size_t missing = (size_t)-offset - ptr;
+        size_t extra = size;
+        while (extra < missing) {
+            extra += extra + size;
+        }
+        tape = realloc(tape, (size + extra) * sizeof(cell));
+        if (!tape) {
+            fail(\"failed to grow the tape\", line, column, replay);
+        }
+        memmove(tape + extra, tape, size * sizeof(cell));
+        memset(tape, 0, extra * sizeof(cell));
+        size += extra;
+        ptr += extra;
+    }
+    return ptr + offset;
+}
+"
+        }
+    }
+}
+
+fn add_function(overflow: Overflow) -> &'static str {
+    match overflow {
+        Overflow::Saturate => {
+            "
+static void add(long long amount, int line, int column, void (*replay)(void)) {
+    cell value = tape[ptr];
+    (void)line;
+    (void)column;
+    (void)replay;
+    if (amount > 0 && (unsigned long long)amount > (unsigned long long)(CELL_MAX - value)) {
+        tape[ptr] = CELL_MAX;
+    } else if (amount < 0 && (unsigned long long)-amount > value) {
+        tape[ptr] = 0;
+    } else {
+        tape[ptr] = (cell)(value + amount);
+    }
+}
+"
+        }
+        // Wrapping additions are written inline
+        Overflow::Trap | Overflow::Wrap => {
+            "
+static void add(long long amount, int line, int column, void (*replay)(void)) {
+    cell value = tape[ptr];
+    if (amount > 0 && (unsigned long long)amount > (unsigned long long)(CELL_MAX - value)) {
+        fail(\"cell overflowed\", line, column, replay);
+    } else if (amount < 0 && (unsigned long long)-amount > value) {
+        fail(\"cell underflowed\", line, column, replay);
+    }
+    tape[ptr] = (cell)(value + amount);
+}
+"
+        }
+    }
+}
+
+fn mul_add_function(overflow: Overflow) -> String {
+    let (overflowed, underflowed) = match overflow {
+        // Multiplying unsigned numbers wraps around like adding them
+        Overflow::Wrap => {
+            return "
+static void mul_add(long long offset, long long factor, int line, int column, void (*replay)(void)) {
+    size_t target;
+    if (!tape[ptr]) {
+        return;
+    }
+    target = offset_index(offset, line, column, replay);
+    tape[target] = (cell)(tape[target] + (unsigned long long)tape[ptr] * (unsigned long long)factor);
+}
+"
+            .to_string()
+        }
+        Overflow::Saturate => ("tape[target] = CELL_MAX;", "tape[target] = 0;"),
+        Overflow::Trap => (
+            "fail(\"cell overflowed\", line, column, replay);",
+            "fail(\"cell underflowed\", line, column, replay);",
+        ),
+    };
+
+    format!(
+        "
+static void mul_add(long long offset, long long factor, int line, int column, void (*replay)(void)) {{
+    cell value = tape[ptr];
+    cell current;
+    size_t target;
+    unsigned long long magnitude = factor < 0 ? 0 - (unsigned long long)factor : (unsigned long long)factor;
+    if (!value) {{
+        return;
+    }}
+    target = offset_index(offset, line, column, replay);
+    current = tape[target];
+    if (factor > 0 && magnitude > (unsigned long long)(CELL_MAX - current) / value) {{
+        {overflowed}
+    }} else if (factor < 0 && magnitude > (unsigned long long)current / value) {{
+        {underflowed}
+    }} else if (factor < 0) {{
+        tape[target] = (cell)(current - magnitude * value);
+    }} else {{
+        tape[target] = (cell)(current + magnitude * value);
+    }}
+}}
+"
+    )
+}
+
+const OUTPUT_FUNCTION: &str = "
+static void output(void) {
+    putchar((unsigned char)tape[ptr]);
+}
+";
+
+fn input_function(eof: Eof) -> String {
+    let at_eof = match eof {
+        Eof::Unchanged => "",
+        Eof::Zero => " else {\n        tape[ptr] = 0;\n    }",
+        Eof::MinusOne => " else {\n        tape[ptr] = CELL_MAX;\n    }",
+    };
+
+    format!(
+        "
+static void input(void) {{
+    int byte;
+    fflush(stdout);
+    byte = getchar();
+    if (byte != EOF) {{
+        tape[ptr] = (cell)byte;
+    }}{at_eof}
+}}
+"
+    )
+}
+
+const DUMP_FUNCTION: &str = "
+static int width(size_t index) {
+    int index_width = snprintf(NULL, 0, \"%zu\", index);
+    int value_width = snprintf(NULL, 0, \"%llu\", (unsigned long long)tape[index]);
+    return index_width > value_width ? index_width : value_width;
+}
+
+static void dump(void) {
+    size_t start = ptr < 8 ? 0 : ptr - 8;
+    size_t end = ptr + 9 < size ? ptr + 9 : size;
+    size_t i;
+    fflush(stdout);
+    fprintf(stderr, \"data pointer at %zu\\n\", ptr);
+    for (i = start; i < end; i++) {
+        fprintf(stderr, i + 1 < end ? \"%*zu \" : \"%*zu\\n\", width(i), i);
+    }
+    for (i = start; i < end; i++) {
+        fprintf(stderr, i + 1 < end ? \"%*llu \" : \"%*llu\\n\", width(i), (unsigned long long)tape[i]);
+    }
+    for (i = start; i <= ptr; i++) {
+        fprintf(stderr, i < ptr ? \"%*s \" : \"%*s\\n\", width(i), i == ptr ? \"^\" : \"\");
+    }
+}
+";
@@ -0,0 +1,73 @@
+//! Code generators that turn a program into source code of another language or into an executable
+//!
+//! Every generator honors the [`Config`](crate::Config) of a machine and its [`Cell`](crate::Cell)
+//! type, so the generated program behaves like running the original one with `bfi`.
+
+mod assembler;
+pub mod c;
+pub mod rust;
+pub mod wasm;
+mod wasm_module;
+pub mod x86_64;
+
+use crate::{
+    config::{Boundary, Config, Overflow},
+    lexer::Position,
+    optimizer::{translate, Op},
+    program::Program,
+};
+
+/// The unoptimized operations of a program, which compiled programs run one by one when an
+/// operation optimized from several instructions fails, to report the instruction that failed like
+/// [`Machine`](crate::Machine) does
+pub(crate) struct Unfolded {
+    ops: Vec<(Op, Position)>,
+    boundary: Boundary,
+    overflow: Overflow,
+}
+
+impl Unfolded {
+    pub(crate) fn new(program: &Program, config: &Config) -> Self {
+        Self {
+            ops: translate(program.instructions()),
+            boundary: config.boundary,
+            overflow: config.overflow,
+        }
+    }
+
+    /// The instructions an operation at a position was optimized from, if there are several and
+    /// the operation can fail
+    ///
+    /// A failed operation has not changed the tape yet, or stops a scan at the cell it could not
+    /// move on from, so running these instructions from there fails at the same instruction as the
+    /// unoptimized program.
+    pub(crate) fn get(&self, op: &Op, position: Position) -> Option<&[(Op, Position)]> {
+        let moves_fail = self.boundary == Boundary::Error;
+        let adds_fail = self.overflow == Overflow::Trap;
+        let count = match *op {
+            Op::Add(amount) if adds_fail && amount.unsigned_abs() > 1 => {
+                amount.unsigned_abs() as usize
+            }
+            Op::Move(offset) if moves_fail && offset.unsigned_abs() > 1 => offset.unsigned_abs(),
+            // A lowered loop is a single loop of the unoptimized program
+            Op::MulAdd { .. } if moves_fail || adds_fail => 1,
+            Op::Scan(_) if moves_fail => 1,
+            _ => return None,
+        };
+
+        // Every operation in a loop lies between the positions of its brackets
+        let mut ops = &self.ops[..];
+        loop {
+            let index = ops
+                .partition_point(|(_, start)| *start <= position)
+                .checked_sub(1)?;
+            match &ops[index] {
+                (_, start) if *start == position => {
+                    return ops.get(index..index + count);
+                }
+                (Op::Loop(body, end), _) if position < *end => ops = body,
+                _ => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,588 @@
+//! Transpiles brainfuck to Rust source code
+
+use std::{fmt::Write, mem};
+
+use super::Unfolded;
+use crate::{
+    cell::Cell,
+    config::{Boundary, Config, Eof, Overflow},
+    lexer::Position,
+    optimizer::Op,
+    program::Program,
+};
+
+/// The indentation of each level of nested blocks
+const INDENT: &str = "    ";
+
+/// Which helper methods the generated code calls
+#[derive(Default)]
+struct Uses {
+    moves: bool,
+    add: bool,
+    mul_add: bool,
+    output: bool,
+    input: bool,
+    dump: bool,
+}
+
+/// Transpiles a program to a Rust module for cells of type `C`, optimized for the configuration
+///
+/// The module has no dependencies and no inner attributes, so it can be written to `OUT_DIR` by a
+/// build script and embedded with [`include!`]. It provides `run` and `run_with` functions that
+/// behave like [`Machine::run`](crate::Machine::run) and
+/// [`Machine::run_with`](crate::Machine::run_with) on a new machine, and an `Error` type with the
+/// same messages as a [`RuntimeError`](crate::RuntimeError).
+///
+/// # Panics
+///
+/// Panics if the data pointer starts at the largest index, which no tape can hold.
+pub fn transpile<C: Cell>(program: &Program, config: &Config) -> String {
+    let optimized = program.optimized_for(config);
+    let ops = optimized.ops();
+    let unfolded = Unfolded::new(program, config);
+    let mut uses = Uses::default();
+    let mut replays = vec![];
+    let mut body = String::new();
+    write_ops::<C>(ops, config, &unfolded, &mut uses, &mut replays, &mut body);
+
+    // A failed operation that was optimized from several instructions runs them one by one
+    let mut methods = String::new();
+    for (index, ops) in replays.iter().enumerate() {
+        let mut statements = String::new();
+        write_ops::<C>(
+            ops,
+            config,
+            &unfolded,
+            &mut uses,
+            &mut vec![],
+            &mut statements,
+        );
+        let _ = write!(
+            methods,
+            "
+    fn replay_{index}(&mut self) -> Result<(), Error> {{
+{statements}        Ok(())
+    }}
+"
+        );
+    }
+
+    let cell = format!("u{}", C::BITS);
+    let size = config
+        .start
+        .checked_add(1)
+        .expect("data pointer starts past the largest tape")
+        .max(config.tape_size);
+    let start = config.start;
+
+    // Errors at the end of the program are attributed to its last instruction
+    let Position { line, column, .. } = last_position(ops);
+
+    let input_attribute = match uses.input {
+        true => "",
+        false => "    #[allow(dead_code)] // The program never reads input\n",
+    };
+    let struct_attribute = match ops.is_empty() {
+        true => "#[allow(dead_code)] // The program is empty\n",
+        false => "",
+    };
+
+    let mut module = format!(
+        "// Generated by bfi
+
+use std::io::{{self, BufWriter, Read, Write}};
+
+/// An error that stopped the program, pointing at the instruction that caused it
+#[derive(Debug)]
+pub struct Error {{
+    pub message: &'static str,
+    pub line: usize,
+    pub column: usize,
+    /// The reason reading the input or writing the output failed
+    pub io: Option<io::Error>,
+}}
+
+impl std::fmt::Display for Error {{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{
+        write!(f, \"{{}} at {{}}:{{}}\", self.message, self.line, self.column)?;
+        match &self.io {{
+            Some(error) => write!(f, \": {{error}}\"),
+            None => Ok(()),
+        }}
+    }}
+}}
+
+impl std::error::Error for Error {{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {{
+        self.io.as_ref().map(|error| error as _)
+    }}
+}}
+
+/// Runs the program, reading from stdin and writing to stdout
+pub fn run() -> Result<(), Error> {{
+    run_with(io::stdin().lock(), io::stdout().lock())
+}}
+
+/// Runs the program, reading input from `input` and writing output to `output`
+///
+/// Output is buffered and flushed before reading input and when the program ends.
+pub fn run_with(input: impl Read, output: impl Write) -> Result<(), Error> {{
+    let mut machine = Machine {{
+        tape: vec![0; {size}],
+        pointer: {start},
+        input,
+        output: BufWriter::new(output),
+    }};
+
+    machine.program()?;
+    machine.output.flush().map_err(|error| Error {{
+        message: \"failed to write output\",
+        line: {line},
+        column: {column},
+        io: Some(error),
+    }})
+}}
+
+{struct_attribute}struct Machine<R: Read, W: Write> {{
+    tape: Vec<{cell}>,
+    pointer: usize,
+{input_attribute}    input: R,
+    output: BufWriter<W>,
+}}
+
+impl<R: Read, W: Write> Machine<R, W> {{
+    fn program(&mut self) -> Result<(), Error> {{
+{body}        Ok(())
+    }}
+{methods}"
+    );
+
+    if uses.moves {
+        module.push_str(offset_index_method(config.boundary));
+    }
+    if uses.add {
+        module.push_str(&add_method(config.overflow, &cell));
+    }
+    if uses.mul_add {
+        module.push_str(&mul_add_method(config, &cell));
+    }
+    if uses.output {
+        module.push_str(OUTPUT_METHOD);
+    }
+    if uses.input {
+        module.push_str(&input_method(config.eof, &cell));
+    }
+    if uses.dump {
+        module.push_str(DUMP_METHOD);
+    }
+    module.push_str("}\n");
+
+    module
+}
+
+/// Transpiles a program to a standalone Rust program for cells of type `C`, optimized for the
+/// configuration
+///
+/// The program consists of the [module](transpile) and a `main` function that runs it, reporting
+/// errors on stderr and exiting with code 3 if the program fails or 4 if reading the input or
+/// writing the output fails, like `bfi`.
+///
+/// # Panics
+///
+/// Panics if the data pointer starts at the largest index, which no tape can hold.
+pub fn transpile_program<C: Cell>(program: &Program, config: &Config) -> String {
+    let mut source = transpile::<C>(program, config);
+    source.push_str(
+        "
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!(\"error: {error}\");
+            std::process::ExitCode::from(if error.io.is_some() { 4 } else { 3 })
+        }
+    }
+}
+",
+    );
+
+    source
+}
+
+/// Writes the statements of the operations, with an explicit stack of the loops they are nested in
+///
+/// The instructions of operations that can fail and were optimized from several are added to
+/// `replays`, and the error of the operation is replaced by the one of running them.
+fn write_ops<'a, C: Cell>(
+    ops: &[(Op, Position)],
+    config: &Config,
+    unfolded: &'a Unfolded,
+    uses: &mut Uses,
+    replays: &mut Vec<&'a [(Op, Position)]>,
+    output: &mut String,
+) {
+    let mut rest = ops.iter();
+
+    // The operations after each loop that is currently open
+    let mut stack = vec![];
+
+    // A failing operation returns its error, which needs the position to be passed along
+    let moves_fail = config.boundary == Boundary::Error;
+    let mul_add_fails = moves_fail || config.overflow == Overflow::Trap;
+
+    loop {
+        let Some((op, position)) = rest.next() else {
+            let Some(outer) = stack.pop() else {
+                return;
+            };
+
+            rest = outer;
+            let _ = writeln!(output, "{}}}", INDENT.repeat(stack.len() + 2));
+            continue;
+        };
+
+        let indent = INDENT.repeat(stack.len() + 2);
+        let Position { line, column, .. } = *position;
+        let fail = match unfolded.get(op, *position) {
+            Some(instructions) => {
+                replays.push(instructions);
+                let index = replays.len() - 1;
+                format!(".map_err(|error| self.replay_{index}().err().unwrap_or(error))?")
+            }
+            None => "?".to_string(),
+        };
+        let offset_index = |offset: isize| match moves_fail {
+            true => format!("self.offset_index({offset}, {line}, {column}){fail}"),
+            false => format!("self.offset_index({offset})"),
+        };
+
+        let _ = match *op {
+            Op::Add(amount) => {
+                if config.overflow == Overflow::Wrap {
+                    // Wrapping by the range of the cell keeps the literal in the range of its type
+                    let amount = i128::from(amount).rem_euclid(1 << C::BITS);
+                    writeln!(
+                        output,
+                        "{indent}self.tape[self.pointer] = self.tape[self.pointer].wrapping_add({amount});"
+                    )
+                } else {
+                    uses.add = true;
+                    match config.overflow {
+                        Overflow::Trap => {
+                            writeln!(
+                                output,
+                                "{indent}self.add({amount}, {line}, {column}){fail};"
+                            )
+                        }
+                        _ => writeln!(output, "{indent}self.add({amount});"),
+                    }
+                }
+            }
+            Op::Move(offset) => {
+                uses.moves = true;
+                writeln!(output, "{indent}self.pointer = {};", offset_index(offset))
+            }
+            Op::Output => {
+                uses.output = true;
+                writeln!(output, "{indent}self.output({line}, {column})?;")
+            }
+            Op::Input => {
+                uses.input = true;
+                writeln!(output, "{indent}self.input({line}, {column})?;")
+            }
+            Op::Dump => {
+                uses.dump = true;
+                writeln!(output, "{indent}self.dump({line}, {column})?;")
+            }
+            Op::Clear => writeln!(output, "{indent}self.tape[self.pointer] = 0;"),
+            Op::MulAdd { offset, factor } => {
+                uses.moves = true;
+                uses.mul_add = true;
+                match mul_add_fails {
+                    true => writeln!(
+                        output,
+                        "{indent}self.mul_add({offset}, {factor}, {line}, {column}){fail};"
+                    ),
+                    false => writeln!(output, "{indent}self.mul_add({offset}, {factor});"),
+                }
+            }
+            Op::Scan(stride) => {
+                uses.moves = true;
+                let _ = writeln!(output, "{indent}while self.tape[self.pointer] != 0 {{");
+                let _ = writeln!(
+                    output,
+                    "{indent}{INDENT}self.pointer = {};",
+                    offset_index(stride)
+                );
+                writeln!(output, "{indent}}}")
+            }
+            Op::Loop(ref body, _) => {
+                let _ = writeln!(output, "{indent}while self.tape[self.pointer] != 0 {{");
+                stack.push(mem::replace(&mut rest, body.iter()));
+                continue;
+            }
+        };
+    }
+}
+
+/// The position of the last operation, which is the `]` if it is a loop
+fn last_position(ops: &[(Op, Position)]) -> Position {
+    match ops.last() {
+        Some((Op::Loop(_, end), _)) => *end,
+        Some((_, position)) => *position,
+        None => Position::default(),
+    }
+}
+
+fn offset_index_method(boundary: Boundary) -> &'static str {
+    match boundary {
+        Boundary::Error => {
+            "
+    fn offset_index(&self, offset: isize, line: usize, column: usize) -> Result<usize, Error> {
+        let message = match self.pointer.checked_add_signed(offset) {
+            Some(index) if index < self.tape.len() => return Ok(index),
+            Some(_) => \"data pointer moved past the end of the tape\",
+            None => \"data pointer moved past the start of the tape\",
+        };
+
+        Err(Error {
+            message,
+            line,
+            column,
+            io: None,
+        })
+    }
+"
+        }
+        Boundary::Wrap => {
+            "
+    fn offset_index(&self, offset: isize) -> usize {
+        let length = self.tape.len() as isize;
+        (self.pointer as isize + offset).rem_euclid(length) as usize
+    }
+"
+        }
+        // Growing the tape at the start moves the data pointer along
+        Boundary::Grow => {
+            "
+    fn offset_index(&mut self, offset: isize) -> usize {
+        let length = self.tape.len();
+        match self.pointer.checked_add_signed(offset) {
+            Some(index) if index < length => index,
+            Some(index) => {
+                let mut grown = length;
+                while grown <= index {
+                    grown *= 2;
+                }
+                self.tape.resize(grown, 0);
+                index
+            }
+            None => {
+                let missing = offset.unsigned_abs() - self.pointer;
+                let mut extra = length;
+                while extra < missing {
+                    extra += extra + length;
+                }
+                self.tape.splice(0..0, std::iter::repeat_n(0, extra));
+                self.pointer += extra;
+                self.pointer - offset.unsigned_abs()
+            }
+        }
+    }
+"
+        }
+    }
+}
+
+fn add_method(overflow: Overflow, cell: &str) -> String {
+    match overflow {
+        Overflow::Trap => format!(
+            "
+    fn add(&mut self, amount: i64, line: usize, column: usize) -> Result<(), Error> {{
+        let value = i128::from(self.tape[self.pointer]) + i128::from(amount);
+        let message = if value > i128::from({cell}::MAX) {{
+            \"cell overflowed\"
+        }} else if value < 0 {{
+            \"cell underflowed\"
+        }} else {{
+            self.tape[self.pointer] = value as {cell};
+            return Ok(());
+        }};
+
+        Err(Error {{
+            message,
+            line,
+            column,
+            io: None,
+        }})
+    }}
+"
+        ),
+        // Wrapping additions are written inline
+        Overflow::Saturate | Overflow::Wrap => format!(
+            "
+    fn add(&mut self, amount: i64) {{
+        let value = i128::from(self.tape[self.pointer]) + i128::from(amount);
+        self.tape[self.pointer] = value.clamp(0, i128::from({cell}::MAX)) as {cell};
+    }}
+"
+        ),
+    }
+}
+
+fn mul_add_method(config: &Config, cell: &str) -> String {
+    let fails = config.boundary == Boundary::Error || config.overflow == Overflow::Trap;
+    let (parameters, result, skip) = match fails {
+        true => (
+            ", line: usize, column: usize",
+            " -> Result<(), Error>",
+            "return Ok(());",
+        ),
+        false => ("", "", "return;"),
+    };
+    let target = match config.boundary {
+        Boundary::Error => "self.offset_index(offset, line, column)?",
+        _ => "self.offset_index(offset)",
+    };
+
+    // The product of two cells fits into an `i128`, but adding the target cell to it might not
+    let sum =
+        "(i128::from(value) * i128::from(factor)).saturating_add(i128::from(self.tape[target]))";
+    let update = match config.overflow {
+        Overflow::Wrap => format!(
+            "self.tape[target] = self.tape[target].wrapping_add(value.wrapping_mul(factor as {cell}));"
+        ),
+        Overflow::Saturate => format!(
+            "let sum = {sum};
+        self.tape[target] = sum.clamp(0, i128::from({cell}::MAX)) as {cell};"
+        ),
+        Overflow::Trap => format!(
+            "let sum = {sum};
+        let message = if sum > i128::from({cell}::MAX) {{
+            \"cell overflowed\"
+        }} else if sum < 0 {{
+            \"cell underflowed\"
+        }} else {{
+            self.tape[target] = sum as {cell};
+            return Ok(());
+        }};
+
+        Err(Error {{
+            message,
+            line,
+            column,
+            io: None,
+        }})"
+        ),
+    };
+    let end = match (fails, config.overflow) {
+        (true, Overflow::Wrap | Overflow::Saturate) => "\n        Ok(())",
+        _ => "",
+    };
+
+    format!(
+        "
+    fn mul_add(&mut self, offset: isize, factor: i64{parameters}){result} {{
+        let value = self.tape[self.pointer];
+        if value == 0 {{
+            {skip}
+        }}
+
+        let target = {target};
+        {update}{end}
+    }}
+"
+    )
+}
+
+const OUTPUT_METHOD: &str = "
+    fn output(&mut self, line: usize, column: usize) -> Result<(), Error> {
+        let byte = self.tape[self.pointer].to_le_bytes()[0];
+        self.output.write_all(&[byte]).map_err(|error| Error {
+            message: \"failed to write output\",
+            line,
+            column,
+            io: Some(error),
+        })
+    }
+";
+
+fn input_method(eof: Eof, cell: &str) -> String {
+    let at_eof = match eof {
+        Eof::Unchanged => "()".to_string(),
+        Eof::Zero => "self.tape[self.pointer] = 0".to_string(),
+        Eof::MinusOne => format!("self.tape[self.pointer] = {cell}::MAX"),
+    };
+    let from_byte = match cell {
+        "u8" => "byte[0]".to_string(),
+        cell => format!("{cell}::from(byte[0])"),
+    };
+
+    format!(
+        "
+    fn input(&mut self, line: usize, column: usize) -> Result<(), Error> {{
+        // Flush first, so prompts are visible before waiting for input
+        self.output.flush().map_err(|error| Error {{
+            message: \"failed to write output\",
+            line,
+            column,
+            io: Some(error),
+        }})?;
+
+        let mut byte = [0];
+        match self.input.read_exact(&mut byte) {{
+            Ok(()) => self.tape[self.pointer] = {from_byte},
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {at_eof},
+            Err(error) => {{
+                return Err(Error {{
+                    message: \"failed to read input\",
+                    line,
+                    column,
+                    io: Some(error),
+                }})
+            }}
+        }}
+
+        Ok(())
+    }}
+"
+    )
+}
+
+const DUMP_METHOD: &str = "
+    fn dump(&mut self, line: usize, column: usize) -> Result<(), Error> {
+        // Flush first, so the dump shows up after the output that came before it
+        self.output.flush().map_err(|error| Error {
+            message: \"failed to write output\",
+            line,
+            column,
+            io: Some(error),
+        })?;
+
+        let start = self.pointer.saturating_sub(8);
+        let end = (self.pointer + 9).min(self.tape.len());
+        let mut indices = String::new();
+        let mut values = String::new();
+        let mut marker = String::new();
+
+        for index in start..end {
+            let value = self.tape[index].to_string();
+            let width = index.to_string().len().max(value.len());
+            let mark = if index == self.pointer { \"^\" } else { \"\" };
+
+            indices.push_str(&format!(\"{index:>width$} \"));
+            values.push_str(&format!(\"{value:>width$} \"));
+            marker.push_str(&format!(\"{mark:>width$} \"));
+        }
+
+        eprintln!(
+            \"data pointer at {}\\n{}\\n{}\\n{}\",
+            self.pointer,
+            indices.trim_end(),
+            values.trim_end(),
+            marker.trim_end()
+        );
+
+        Ok(())
+    }
+";
@@ -0,0 +1,1071 @@
+//! Compiles brainfuck to a WebAssembly module, with the tape in its linear memory
+
+use std::{fmt, mem};
+
+use super::{
+    wasm_module::{
+        Func, Global,
+        Instr::{self, *},
+        Local, Module, Num, Type,
+    },
+    Unfolded,
+};
+use crate::{
+    cell::Cell,
+    config::{Boundary, Config, Eof, Overflow},
+    lexer::Position,
+    optimizer::Op,
+    program::Program,
+};
+
+/// How a module reads its input and writes its output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interface {
+    /// Imports `read` and `write` functions from the host and exports a `run` function
+    Host,
+    /// Reads from stdin and writes to stdout through WASI preview 1, so the module runs as a
+    /// command in runtimes like wasmtime
+    Wasi,
+}
+
+/// The size of a page of memory
+const PAGE_SIZE: u64 = 1 << 16;
+
+/// The most pages a memory with 32-bit addresses can have, which add up to 4 GiB
+const MAX_PAGES: u64 = 1 << 16;
+
+/// The codes `run` returns when the program fails
+const PAST_START: i32 = 1;
+const PAST_END: i32 = 2;
+const OVERFLOW: i32 = 3;
+const UNDERFLOW: i32 = 4;
+const GROW_FAILED: i32 = 5;
+/// Only returned with WASI, where the module exits with code 4 for them
+const INPUT_FAILED: i32 = 6;
+const OUTPUT_FAILED: i32 = 7;
+
+/// The error messages for each code
+const MESSAGES: [&str; 7] = [
+    "data pointer moved past the start of the tape",
+    "data pointer moved past the end of the tape",
+    "cell overflowed",
+    "cell underflowed",
+    "failed to grow the tape",
+    "failed to read input",
+    "failed to write output",
+];
+
+/// The layout of the memory of a WASI module in front of the tape
+const IO_VECTOR: i32 = 0;
+/// Where WASI functions store the amount of bytes they read or wrote
+const COUNT: i32 = 8;
+/// The end of the space that numbers are formatted into, from back to front
+const NUMBER_END: i32 = 24;
+/// The table of addresses of the messages for each code, followed by the strings
+const STRINGS: u32 = 24;
+const OUTPUT_BUFFER: i32 = 1024;
+const INPUT_BUFFER: i32 = OUTPUT_BUFFER + BUFFER_SIZE;
+const BUFFER_SIZE: i32 = 4096;
+const WASI_TAPE: u32 = (INPUT_BUFFER + BUFFER_SIZE) as u32;
+
+/// The reason a program cannot be compiled to a WebAssembly module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The tape does not fit into the memory a module can address
+    TapeTooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TapeTooLarge => write!(
+                f,
+                "the tape does not fit into the 4 GiB of memory of a WebAssembly module"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Compiles a program to a binary WebAssembly module for cells of type `C`, optimized for the
+/// configuration
+///
+/// The cells are stored in little-endian order in the exported `memory`. With the
+/// [`Host`](Interface::Host) interface, the module imports two functions from `env`:
+/// - `read: () -> i32` returns the next byte of input, or -1 at the end of the input
+/// - `write: (i32) -> ()` writes a byte of output
+///
+/// It exports a function `run: () -> i32`, which returns 0 when the program finished. Otherwise
+/// it returns the code of an error and sets the exported `line` and `column` globals to the
+/// position of the instruction that caused it: 1 if the data pointer moved past the start of the
+/// tape, 2 if it moved past the end, 3 if a cell overflowed, 4 if a cell underflowed or 5 if the
+/// tape could not grow. The tape starts at address 0.
+///
+/// With [WASI](Interface::Wasi), the module is a command that reports errors on stderr and exits
+/// with code 3 if the program fails or 4 if reading the input or writing the output fails, like
+/// `bfi`. `#` is ignored with both interfaces.
+///
+/// Returns an error if the tape does not fit into the 4 GiB of memory a module can address.
+pub fn compile<C: Cell>(
+    program: &Program,
+    config: &Config,
+    interface: Interface,
+) -> Result<Vec<u8>, Error> {
+    Ok(module::<C>(program, config, interface)?.binary())
+}
+
+/// Compiles a program to a WebAssembly module in the text format for cells of type `C`
+///
+/// The module is the same as the [binary](compile) one.
+pub fn transpile<C: Cell>(
+    program: &Program,
+    config: &Config,
+    interface: Interface,
+) -> Result<String, Error> {
+    Ok(module::<C>(program, config, interface)?.text())
+}
+
+fn module<C: Cell>(
+    program: &Program,
+    config: &Config,
+    interface: Interface,
+) -> Result<Module, Error> {
+    let bytes = (C::BITS / 8) as u8;
+    let size = config
+        .start
+        .checked_add(1)
+        .map(|end| end.max(config.tape_size))
+        .ok_or(Error::TapeTooLarge)?;
+    let base = match interface {
+        Interface::Host => 0,
+        Interface::Wasi => WASI_TAPE,
+    };
+    let pages = (size as u64)
+        .checked_mul(u64::from(bytes))
+        .and_then(|tape| tape.checked_add(u64::from(base)))
+        .map(|memory| memory.div_ceil(PAGE_SIZE).max(1))
+        .filter(|&pages| pages <= MAX_PAGES)
+        .ok_or(Error::TapeTooLarge)?;
+    let mut module = Module::new(pages as u32);
+
+    let io = match interface {
+        Interface::Host => Io::Host {
+            read: module.import("env", "read", &[], Some(Type::I32)),
+            write: module.import("env", "write", &[Type::I32], None),
+        },
+        Interface::Wasi => {
+            let fd = [Type::I32; 4];
+            let fd_write =
+                module.import("wasi_snapshot_preview1", "fd_write", &fd, Some(Type::I32));
+            let fd_read = module.import("wasi_snapshot_preview1", "fd_read", &fd, Some(Type::I32));
+            let proc_exit =
+                module.import("wasi_snapshot_preview1", "proc_exit", &[Type::I32], None);
+            Io::Wasi(wasi(&mut module, fd_write, fd_read, proc_exit))
+        }
+    };
+
+    let export = |name| match interface {
+        Interface::Host => Some(name),
+        Interface::Wasi => None,
+    };
+    let line = module.global("line", Type::I32, 0, export("line"));
+    let column = module.global("column", Type::I32, 0, export("column"));
+
+    let run = module.function("run", &[], Some(Type::I32));
+    let grow = match config.boundary {
+        Boundary::Grow => Some(grow_function(&mut module, base, bytes, size)),
+        _ => None,
+    };
+
+    let unfolded = Unfolded::new(program, config);
+    let mut compiler = Compiler {
+        config,
+        unfolded: &unfolded,
+        replay: None,
+        io,
+        bytes,
+        max: u64::MAX >> (64 - C::BITS),
+        base,
+        size,
+        code: vec![],
+        line,
+        column,
+        grow,
+        pointer: module.local(run, "pointer", Type::I64),
+        index: module.local(run, "index", Type::I64),
+        grown: module.local(run, "grown", Type::I64),
+        value: module.local(run, "value", Type::I64),
+        old: module.local(run, "old", Type::I64),
+        result: module.local(run, "result", Type::I64),
+        byte: module.local(run, "byte", Type::I32),
+    };
+    compiler.run(program.optimized_for(config).ops());
+    module.define(run, compiler.code);
+
+    match interface {
+        Interface::Host => module.export(run, "run"),
+        Interface::Wasi => {
+            let start = start_function(&mut module, io, run, line, column);
+            module.export(start, "_start");
+        }
+    }
+
+    Ok(module)
+}
+
+/// The functions the compiled program reads and writes with
+#[derive(Clone, Copy)]
+enum Io {
+    /// Imported from the host
+    Host {
+        read: Func,
+        write: Func,
+    },
+    Wasi(Wasi),
+}
+
+/// The functions of a WASI module that buffer the input and output
+#[derive(Clone, Copy)]
+struct Wasi {
+    proc_exit: Func,
+    /// Writes the buffered output, returning 0 if that failed
+    flush: Func,
+    /// Buffers a byte, returning 0 if writing the output failed
+    write: Func,
+    /// Flushes the output and reads a byte, returning -1 at the end of the input or the negative
+    /// code of an error
+    read: Func,
+    /// Writes the length-prefixed string at an address to stderr
+    print: Func,
+    /// Writes a number in decimal to stderr
+    print_number: Func,
+    /// The addresses of the strings in memory
+    error: i32,
+    at: i32,
+    colon: i32,
+    newline: i32,
+    /// The address of the table of messages
+    messages: i32,
+}
+
+struct Compiler<'a> {
+    config: &'a Config,
+    unfolded: &'a Unfolded,
+    /// The instructions the operation being compiled was optimized from, if it can fail and there
+    /// are several
+    replay: Option<&'a [(Op, Position)]>,
+    io: Io,
+    /// The width of a cell in bytes
+    bytes: u8,
+    max: u64,
+    /// The address of the first cell
+    base: u32,
+    /// The initial length of the tape
+    size: usize,
+    code: Vec<Instr>,
+    /// The position of the instruction that failed
+    line: Global,
+    column: Global,
+    /// The function that grows the tape to include an index, if it can grow
+    grow: Option<(Func, Global)>,
+    /// The locals of `run`
+    pointer: Local,
+    index: Local,
+    grown: Local,
+    value: Local,
+    old: Local,
+    result: Local,
+    byte: Local,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, code: &[Instr]) {
+        self.code.extend_from_slice(code);
+    }
+
+    fn run(&mut self, ops: &[(Op, Position)]) {
+        self.emit(&[I64Const(self.config.start as i64), LocalSet(self.pointer)]);
+        self.block(ops);
+
+        if let Io::Wasi(wasi) = self.io {
+            // Errors at the end of the program are attributed to its last operation
+            let last = match ops.last() {
+                Some((Op::Loop(_, end), _)) => *end,
+                Some((_, position)) => *position,
+                None => Position::default(),
+            };
+            self.emit(&[Call(wasi.flush), I32(Num::Eqz), If]);
+            self.fail(OUTPUT_FAILED, last);
+            self.emit(&[End]);
+        }
+
+        self.emit(&[I32Const(0)]);
+    }
+
+    /// Compiles operations with an explicit stack of the loops they are nested in, so deeply nested
+    /// loops cannot overflow the stack
+    fn block(&mut self, ops: &[(Op, Position)]) {
+        let mut rest = ops.iter();
+
+        // The operations after each loop that is currently open
+        let mut stack = vec![];
+
+        loop {
+            let Some((op, position)) = rest.next() else {
+                let Some(outer) = stack.pop() else {
+                    return;
+                };
+
+                self.emit(&[Br(0), End, End]);
+                rest = outer;
+                continue;
+            };
+            let position = *position;
+            self.replay = self.unfolded.get(op, position);
+
+            match *op {
+                Op::Add(amount) => self.add(amount, position),
+                Op::Move(offset) => {
+                    self.resolve(offset, position);
+                    self.emit(&[LocalGet(self.index), LocalSet(self.pointer)]);
+                }
+                Op::Output => {
+                    self.address(self.pointer);
+                    self.emit(&[Load(Type::I32, 1, self.base)]);
+                    match self.io {
+                        Io::Host { write, .. } => self.emit(&[Call(write)]),
+                        Io::Wasi(wasi) => {
+                            self.emit(&[Call(wasi.write), I32(Num::Eqz), If]);
+                            self.fail(OUTPUT_FAILED, position);
+                            self.emit(&[End]);
+                        }
+                    }
+                }
+                Op::Input => self.input(position),
+                // Dumping the tape is not supported
+                Op::Dump => (),
+                Op::Clear => {
+                    self.address(self.pointer);
+                    self.emit(&[I64Const(0)]);
+                    self.store();
+                }
+                Op::MulAdd { offset, factor } => self.mul_add(offset, factor, position),
+                Op::Scan(stride) => {
+                    self.emit(&[Block, Loop]);
+                    self.address(self.pointer);
+                    self.load();
+                    self.emit(&[I64(Num::Eqz), BrIf(1)]);
+                    self.resolve(stride, position);
+                    self.emit(&[
+                        LocalGet(self.index),
+                        LocalSet(self.pointer),
+                        Br(0),
+                        End,
+                        End,
+                    ]);
+                }
+                Op::Loop(ref body, _) => {
+                    self.emit(&[Block, Loop]);
+                    self.address(self.pointer);
+                    self.load();
+                    self.emit(&[I64(Num::Eqz), BrIf(1)]);
+                    stack.push(mem::replace(&mut rest, body.iter()));
+                }
+            }
+        }
+    }
+
+    /// Pushes the address of the cell at the index in a local, relative to the start of the tape
+    fn address(&mut self, index: Local) {
+        self.emit(&[LocalGet(index), I32WrapI64]);
+        if self.bytes > 1 {
+            self.emit(&[I32Const(self.bytes.into()), I32(Num::Mul)]);
+        }
+    }
+
+    /// Loads the cell at the address on the stack as an `i64`
+    fn load(&mut self) {
+        self.emit(&[Load(Type::I64, self.bytes, self.base)]);
+    }
+
+    /// Stores the value on the stack in the cell at the address below it
+    fn store(&mut self) {
+        self.emit(&[Store(Type::I64, self.bytes, self.base)]);
+    }
+
+    /// Pushes the length of the tape
+    fn length(&self) -> Instr {
+        match self.grow {
+            Some((_, length)) => GlobalGet(length),
+            None => I64Const(self.size as i64),
+        }
+    }
+
+    /// Returns the code of an error from `run`, after setting the position of the instruction
+    fn fail(&mut self, code: i32, position: Position) {
+        self.locate(position);
+        self.emit(&[I32Const(code), Return]);
+    }
+
+    /// Runs the instructions the failed operation was optimized from one by one, so the one that
+    /// fails returns its error
+    fn replay(&mut self) {
+        if let Some(ops) = self.replay {
+            self.block(ops);
+            self.replay = Some(ops);
+        }
+    }
+
+    fn locate(&mut self, position: Position) {
+        self.emit(&[
+            I32Const(position.line as i32),
+            GlobalSet(self.line),
+            I32Const(position.column as i32),
+            GlobalSet(self.column),
+        ]);
+    }
+
+    fn input(&mut self, position: Position) {
+        match self.io {
+            Io::Host { read, .. } => self.emit(&[Call(read), LocalSet(self.byte)]),
+            Io::Wasi(wasi) => {
+                self.emit(&[
+                    Call(wasi.read),
+                    LocalTee(self.byte),
+                    I32Const(-1),
+                    I32(Num::LtS),
+                    If,
+                ]);
+                self.locate(position);
+                self.emit(&[I32Const(0), LocalGet(self.byte), I32(Num::Sub), Return, End]);
+            }
+        }
+
+        self.emit(&[LocalGet(self.byte), I32Const(0), I32(Num::GeS), If]);
+        self.address(self.pointer);
+        self.emit(&[LocalGet(self.byte), I64ExtendI32U]);
+        self.store();
+
+        let at_eof = match self.config.eof {
+            Eof::Unchanged => None,
+            Eof::Zero => Some(0),
+            // Storing -1 sets every bit of the cell
+            Eof::MinusOne => Some(-1),
+        };
+        if let Some(value) = at_eof {
+            self.emit(&[Else]);
+            self.address(self.pointer);
+            self.emit(&[I64Const(value)]);
+            self.store();
+        }
+        self.emit(&[End]);
+    }
+
+    /// Adds an amount to the current cell
+    fn add(&mut self, amount: i64, position: Position) {
+        self.address(self.pointer);
+
+        if self.config.overflow == Overflow::Wrap {
+            // Storing only the lowest bytes wraps the sum around
+            self.address(self.pointer);
+            self.load();
+            self.emit(&[I64Const(amount), I64(Num::Add)]);
+            self.store();
+            return;
+        }
+
+        let negative = amount < 0;
+        let magnitude = amount.unsigned_abs();
+
+        if magnitude > self.max {
+            self.overflowed(negative, position);
+        } else {
+            self.address(self.pointer);
+            self.load();
+            self.emit(&[LocalSet(self.old)]);
+            self.checked(negative, I64Const(magnitude as i64), position);
+        }
+
+        self.emit(&[LocalGet(self.result)]);
+        self.store();
+    }
+
+    /// Adds the current cell multiplied by a factor to the cell at an offset
+    fn mul_add(&mut self, offset: isize, factor: i64, position: Position) {
+        self.address(self.pointer);
+        self.load();
+        self.emit(&[LocalTee(self.value), I64(Num::Eqz), I32(Num::Eqz), If]);
+        self.resolve(offset, position);
+        self.address(self.index);
+
+        if self.config.overflow == Overflow::Wrap {
+            // The lowest bytes of a product do not depend on the higher bytes of its factors
+            self.address(self.index);
+            self.load();
+            self.emit(&[
+                LocalGet(self.value),
+                I64Const(factor),
+                I64(Num::Mul),
+                I64(Num::Add),
+            ]);
+        } else {
+            let negative = factor < 0;
+            let magnitude = factor.unsigned_abs().max(1);
+
+            // The product overflows the cell if the value is larger than this
+            self.emit(&[
+                LocalGet(self.value),
+                I64Const((self.max / magnitude) as i64),
+                I64(Num::GtU),
+                If,
+            ]);
+            self.overflowed(negative, position);
+            self.emit(&[
+                Else,
+                LocalGet(self.value),
+                I64Const(magnitude as i64),
+                I64(Num::Mul),
+                LocalSet(self.value),
+            ]);
+            self.address(self.index);
+            self.load();
+            self.emit(&[LocalSet(self.old)]);
+            self.checked(negative, LocalGet(self.value), position);
+            self.emit(&[End, LocalGet(self.result)]);
+        }
+
+        self.store();
+        self.emit(&[End]);
+    }
+
+    /// Adds an amount to or subtracts it from the cell value in `old`, storing the result in
+    /// `result` unless it overflows
+    fn checked(&mut self, negative: bool, amount: Instr, position: Position) {
+        if negative {
+            self.emit(&[LocalGet(self.old), amount, I64(Num::LtU), If]);
+            self.overflowed(true, position);
+            self.emit(&[
+                Else,
+                LocalGet(self.old),
+                amount,
+                I64(Num::Sub),
+                LocalSet(self.result),
+                End,
+            ]);
+        } else {
+            self.emit(&[
+                LocalGet(self.old),
+                amount,
+                I64(Num::Add),
+                LocalTee(self.result),
+            ]);
+            // The sum of 64-bit cells wraps around instead of exceeding the maximum
+            match self.bytes {
+                8 => self.emit(&[amount, I64(Num::LtU), If]),
+                _ => self.emit(&[I64Const(self.max as i64), I64(Num::GtU), If]),
+            }
+            self.overflowed(false, position);
+            self.emit(&[End]);
+        }
+    }
+
+    /// Fails because a cell overflowed or underflowed, or saturates `result`
+    fn overflowed(&mut self, negative: bool, position: Position) {
+        match (self.config.overflow, negative) {
+            (Overflow::Saturate, false) => {
+                self.emit(&[I64Const(self.max as i64), LocalSet(self.result)])
+            }
+            (Overflow::Saturate, true) => self.emit(&[I64Const(0), LocalSet(self.result)]),
+            (_, negative) => {
+                self.replay();
+                self.fail(if negative { UNDERFLOW } else { OVERFLOW }, position);
+            }
+        }
+    }
+
+    /// Computes the index of the cell `offset` cells away from the data pointer into `index`,
+    /// handling indices outside of the tape like the boundary behavior says
+    fn resolve(&mut self, offset: isize, position: Position) {
+        let length = self.length();
+
+        // Negative indices are larger than any length when compared without sign
+        self.emit(&[
+            LocalGet(self.pointer),
+            I64Const(offset as i64),
+            I64(Num::Add),
+            LocalTee(self.index),
+            length,
+            I64(Num::GeU),
+            If,
+        ]);
+
+        match self.grow {
+            None if self.config.boundary == Boundary::Wrap => self.emit(&[
+                LocalGet(self.index),
+                length,
+                I64(Num::RemS),
+                length,
+                I64(Num::Add),
+                length,
+                I64(Num::RemU),
+                LocalSet(self.index),
+            ]),
+            None => {
+                self.replay();
+                self.locate(position);
+                self.emit(&[
+                    I32Const(PAST_START),
+                    I32Const(PAST_END),
+                    LocalGet(self.index),
+                    I64Const(0),
+                    I64(Num::LtS),
+                    Select,
+                    Return,
+                ]);
+            }
+            Some((grow, _)) => {
+                self.emit(&[
+                    LocalGet(self.index),
+                    Call(grow),
+                    LocalTee(self.grown),
+                    I64Const(0),
+                    I64(Num::LtS),
+                    If,
+                ]);
+                self.fail(GROW_FAILED, position);
+                // Growing the start of the tape moves all cells, including the current one
+                self.emit(&[
+                    End,
+                    LocalGet(self.pointer),
+                    LocalGet(self.grown),
+                    LocalGet(self.index),
+                    I64(Num::Sub),
+                    I64(Num::Add),
+                    LocalSet(self.pointer),
+                    LocalGet(self.grown),
+                    LocalSet(self.index),
+                ]);
+            }
+        }
+
+        self.emit(&[End]);
+    }
+}
+
+/// Adds the function that grows the tape to include an index, returning the index on the grown
+/// tape or -1 if the memory cannot grow, along with the global of the length of the tape
+fn grow_function(module: &mut Module, base: u32, bytes: u8, size: usize) -> (Func, Global) {
+    let length = module.global("length", Type::I64, size as i64, None);
+
+    let reserve = module.function("reserve", &[("length", Type::I64)], Some(Type::I32));
+    let new_length = module.param(reserve, 0);
+    let pages = module.local(reserve, "pages", Type::I64);
+    // Makes sure the memory holds a tape of a length, returning 0 if it cannot grow
+    module.define(
+        reserve,
+        vec![
+            LocalGet(new_length),
+            I64Const(bytes.into()),
+            I64(Num::Mul),
+            I64Const((u64::from(base) + PAGE_SIZE - 1) as i64),
+            I64(Num::Add),
+            I64Const(PAGE_SIZE.trailing_zeros().into()),
+            I64(Num::ShrU),
+            MemorySize,
+            I64ExtendI32U,
+            I64(Num::Sub),
+            LocalTee(pages),
+            I64Const(0),
+            I64(Num::GtS),
+            If,
+            LocalGet(pages),
+            I32WrapI64,
+            MemoryGrow,
+            I32Const(-1),
+            I32(Num::Eq),
+            If,
+            I32Const(0),
+            Return,
+            End,
+            End,
+            I32Const(1),
+        ],
+    );
+
+    let grow = module.function("grow", &[("index", Type::I64)], Some(Type::I64));
+    let index = module.param(grow, 0);
+    let extra = module.local(grow, "extra", Type::I64);
+    let to_address = [I64Const(bytes.into()), I64(Num::Mul), I32WrapI64];
+    let mut code = vec![
+        LocalGet(index),
+        I64Const(0),
+        I64(Num::LtS),
+        If,
+        // Adds at least as many cells as the tape has in front of it, moving its contents
+        I64Const(0),
+        LocalGet(index),
+        I64(Num::Sub),
+        LocalTee(extra),
+        GlobalGet(length),
+        LocalGet(extra),
+        GlobalGet(length),
+        I64(Num::GtU),
+        Select,
+        LocalTee(extra),
+        GlobalGet(length),
+        I64(Num::Add),
+        Call(reserve),
+        I32(Num::Eqz),
+        If,
+        I64Const(-1),
+        Return,
+        End,
+        LocalGet(extra),
+    ];
+    code.extend_from_slice(&to_address);
+    code.extend_from_slice(&[I32Const(base as i32), I32(Num::Add), I32Const(base as i32)]);
+    code.push(GlobalGet(length));
+    code.extend_from_slice(&to_address);
+    code.extend_from_slice(&[
+        MemoryCopy,
+        I32Const(base as i32),
+        I32Const(0),
+        LocalGet(extra),
+    ]);
+    code.extend_from_slice(&to_address);
+    code.extend_from_slice(&[
+        MemoryFill,
+        GlobalGet(length),
+        LocalGet(extra),
+        I64(Num::Add),
+        GlobalSet(length),
+        LocalGet(index),
+        LocalGet(extra),
+        I64(Num::Add),
+        Return,
+        End,
+        // Grows the end of the tape to at least twice its length
+        LocalGet(index),
+        I64Const(1),
+        I64(Num::Add),
+        LocalTee(extra),
+        GlobalGet(length),
+        I64Const(2),
+        I64(Num::Mul),
+        LocalGet(extra),
+        GlobalGet(length),
+        I64Const(2),
+        I64(Num::Mul),
+        I64(Num::GtU),
+        Select,
+        LocalTee(extra),
+        Call(reserve),
+        I32(Num::Eqz),
+        If,
+        I64Const(-1),
+        Return,
+        End,
+        LocalGet(extra),
+        GlobalSet(length),
+        LocalGet(index),
+    ]);
+    module.define(grow, code);
+
+    (grow, length)
+}
+
+/// Adds the functions that buffer the input and output of a WASI module, and the strings of its
+/// error messages
+fn wasi(module: &mut Module, fd_write: Func, fd_read: Func, proc_exit: Func) -> Wasi {
+    let output_length = module.global("output_length", Type::I32, 0, None);
+    let input_position = module.global("input_position", Type::I32, 0, None);
+    let input_length = module.global("input_length", Type::I32, 0, None);
+
+    // The table of messages comes first, so the address of a message is at 4 times its code
+    let mut strings = vec![0; 4 * MESSAGES.len()];
+    let mut string = |text: &str| {
+        let address = STRINGS as usize + strings.len();
+        strings.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        strings.extend_from_slice(text.as_bytes());
+        address as i32
+    };
+    let addresses: Vec<_> = MESSAGES.iter().map(|message| string(message)).collect();
+    let (error, at, colon, newline) =
+        (string("error: "), string(" at "), string(":"), string("\n"));
+    for (code, address) in addresses.into_iter().enumerate() {
+        strings[4 * code..4 * code + 4].copy_from_slice(&address.to_le_bytes());
+    }
+    assert!(STRINGS as usize + strings.len() <= OUTPUT_BUFFER as usize);
+    module.data(STRINGS, strings);
+
+    // Writes a number of bytes at an address to a file descriptor, returning 0 if that failed
+    let write_all = module.function(
+        "write_all",
+        &[
+            ("fd", Type::I32),
+            ("address", Type::I32),
+            ("length", Type::I32),
+        ],
+        Some(Type::I32),
+    );
+    let (fd, address, length) = (
+        module.param(write_all, 0),
+        module.param(write_all, 1),
+        module.param(write_all, 2),
+    );
+    let written = module.local(write_all, "written", Type::I32);
+    module.define(
+        write_all,
+        vec![
+            Block,
+            Loop,
+            LocalGet(length),
+            I32(Num::Eqz),
+            BrIf(1),
+            I32Const(IO_VECTOR),
+            LocalGet(address),
+            Store(Type::I32, 4, 0),
+            I32Const(IO_VECTOR),
+            LocalGet(length),
+            Store(Type::I32, 4, 4),
+            LocalGet(fd),
+            I32Const(IO_VECTOR),
+            I32Const(1),
+            I32Const(COUNT),
+            Call(fd_write),
+            If,
+            I32Const(0),
+            Return,
+            End,
+            I32Const(COUNT),
+            Load(Type::I32, 4, 0),
+            LocalTee(written),
+            I32(Num::Eqz),
+            If,
+            I32Const(0),
+            Return,
+            End,
+            LocalGet(address),
+            LocalGet(written),
+            I32(Num::Add),
+            LocalSet(address),
+            LocalGet(length),
+            LocalGet(written),
+            I32(Num::Sub),
+            LocalSet(length),
+            Br(0),
+            End,
+            End,
+            I32Const(1),
+        ],
+    );
+
+    let flush = module.function("flush", &[], Some(Type::I32));
+    let length = module.local(flush, "length", Type::I32);
+    module.define(
+        flush,
+        vec![
+            GlobalGet(output_length),
+            LocalSet(length),
+            I32Const(0),
+            GlobalSet(output_length),
+            I32Const(1),
+            I32Const(OUTPUT_BUFFER),
+            LocalGet(length),
+            Call(write_all),
+        ],
+    );
+
+    let write = module.function("write", &[("byte", Type::I32)], Some(Type::I32));
+    let byte = module.param(write, 0);
+    module.define(
+        write,
+        vec![
+            GlobalGet(output_length),
+            LocalGet(byte),
+            Store(Type::I32, 1, OUTPUT_BUFFER as u32),
+            GlobalGet(output_length),
+            I32Const(1),
+            I32(Num::Add),
+            GlobalSet(output_length),
+            GlobalGet(output_length),
+            I32Const(BUFFER_SIZE),
+            I32(Num::GeU),
+            If,
+            Call(flush),
+            Return,
+            End,
+            I32Const(1),
+        ],
+    );
+
+    let read = module.function("read", &[], Some(Type::I32));
+    let count = module.local(read, "count", Type::I32);
+    module.define(
+        read,
+        vec![
+            // Flush first, so prompts are visible before waiting for input
+            Call(flush),
+            I32(Num::Eqz),
+            If,
+            I32Const(-OUTPUT_FAILED),
+            Return,
+            End,
+            GlobalGet(input_position),
+            GlobalGet(input_length),
+            I32(Num::GeU),
+            If,
+            I32Const(IO_VECTOR),
+            I32Const(INPUT_BUFFER),
+            Store(Type::I32, 4, 0),
+            I32Const(IO_VECTOR),
+            I32Const(BUFFER_SIZE),
+            Store(Type::I32, 4, 4),
+            I32Const(0),
+            I32Const(IO_VECTOR),
+            I32Const(1),
+            I32Const(COUNT),
+            Call(fd_read),
+            If,
+            I32Const(-INPUT_FAILED),
+            Return,
+            End,
+            I32Const(COUNT),
+            Load(Type::I32, 4, 0),
+            LocalTee(count),
+            I32(Num::Eqz),
+            If,
+            I32Const(-1),
+            Return,
+            End,
+            LocalGet(count),
+            GlobalSet(input_length),
+            I32Const(0),
+            GlobalSet(input_position),
+            End,
+            GlobalGet(input_position),
+            Load(Type::I32, 1, INPUT_BUFFER as u32),
+            GlobalGet(input_position),
+            I32Const(1),
+            I32(Num::Add),
+            GlobalSet(input_position),
+        ],
+    );
+
+    let print = module.function("print", &[("string", Type::I32)], None);
+    let string = module.param(print, 0);
+    module.define(
+        print,
+        vec![
+            I32Const(2),
+            LocalGet(string),
+            I32Const(4),
+            I32(Num::Add),
+            LocalGet(string),
+            Load(Type::I32, 4, 0),
+            Call(write_all),
+            Drop,
+        ],
+    );
+
+    let print_number = module.function("print_number", &[("number", Type::I32)], None);
+    let number = module.param(print_number, 0);
+    let position = module.local(print_number, "position", Type::I32);
+    module.define(
+        print_number,
+        vec![
+            I32Const(NUMBER_END),
+            LocalSet(position),
+            Loop,
+            LocalGet(position),
+            I32Const(1),
+            I32(Num::Sub),
+            LocalTee(position),
+            LocalGet(number),
+            I32Const(10),
+            I32(Num::RemU),
+            I32Const(b'0'.into()),
+            I32(Num::Add),
+            Store(Type::I32, 1, 0),
+            LocalGet(number),
+            I32Const(10),
+            I32(Num::DivU),
+            LocalTee(number),
+            BrIf(0),
+            End,
+            I32Const(2),
+            LocalGet(position),
+            I32Const(NUMBER_END),
+            LocalGet(position),
+            I32(Num::Sub),
+            Call(write_all),
+            Drop,
+        ],
+    );
+
+    Wasi {
+        proc_exit,
+        flush,
+        write,
+        read,
+        print,
+        print_number,
+        error,
+        at,
+        colon,
+        newline,
+        messages: STRINGS as i32,
+    }
+}
+
+/// Adds the entry point of a WASI command, which runs the program and reports its errors
+fn start_function(module: &mut Module, io: Io, run: Func, line: Global, column: Global) -> Func {
+    let Io::Wasi(wasi) = io else {
+        unreachable!("only WASI modules have an entry point");
+    };
+
+    let start = module.function("_start", &[], None);
+    let code = module.local(start, "code", Type::I32);
+    module.define(
+        start,
+        vec![
+            Call(run),
+            LocalTee(code),
+            I32(Num::Eqz),
+            If,
+            Return,
+            End,
+            // The output comes before the error, even if it cannot be written
+            Call(wasi.flush),
+            Drop,
+            I32Const(wasi.error),
+            Call(wasi.print),
+            LocalGet(code),
+            I32Const(4),
+            I32(Num::Mul),
+            // Codes start at 1
+            Load(Type::I32, 4, wasi.messages as u32 - 4),
+            Call(wasi.print),
+            I32Const(wasi.at),
+            Call(wasi.print),
+            GlobalGet(line),
+            Call(wasi.print_number),
+            I32Const(wasi.colon),
+            Call(wasi.print),
+            GlobalGet(column),
+            Call(wasi.print_number),
+            I32Const(wasi.newline),
+            Call(wasi.print),
+            I32Const(4),
+            I32Const(3),
+            LocalGet(code),
+            I32Const(INPUT_FAILED),
+            I32(Num::GeU),
+            Select,
+            Call(wasi.proc_exit),
+        ],
+    );
+
+    start
+}
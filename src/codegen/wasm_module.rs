@@ -0,0 +1,689 @@
+//! A minimal builder for the subset of WebAssembly the wasm backend uses, which writes modules in
+//! the text or the binary format
+
+use std::fmt::Write;
+
+/// The type of a value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Type {
+    I32,
+    I64,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::I32 => "i32",
+            Type::I64 => "i64",
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            Type::I32 => 0x7f,
+            Type::I64 => 0x7e,
+        }
+    }
+}
+
+/// The index of a function, counting imported functions first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Func(u32);
+
+/// The index of a parameter or local variable of a function
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Local(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Global(u32);
+
+/// A numeric instruction, which exists for both `i32` and `i64`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Num {
+    Eqz,
+    Eq,
+    LtS,
+    LtU,
+    GtS,
+    GtU,
+    GeS,
+    GeU,
+    Add,
+    Sub,
+    Mul,
+    DivU,
+    RemS,
+    RemU,
+    ShrU,
+}
+
+impl Num {
+    fn name(self) -> &'static str {
+        match self {
+            Num::Eqz => "eqz",
+            Num::Eq => "eq",
+            Num::LtS => "lt_s",
+            Num::LtU => "lt_u",
+            Num::GtS => "gt_s",
+            Num::GtU => "gt_u",
+            Num::GeS => "ge_s",
+            Num::GeU => "ge_u",
+            Num::Add => "add",
+            Num::Sub => "sub",
+            Num::Mul => "mul",
+            Num::DivU => "div_u",
+            Num::RemS => "rem_s",
+            Num::RemU => "rem_u",
+            Num::ShrU => "shr_u",
+        }
+    }
+
+    fn opcode(self, ty: Type) -> u8 {
+        let (comparison, arithmetic) = match ty {
+            Type::I32 => (0x45, 0x6a),
+            Type::I64 => (0x50, 0x7c),
+        };
+
+        match self {
+            Num::Eqz => comparison,
+            Num::Eq => comparison + 1,
+            Num::LtS => comparison + 3,
+            Num::LtU => comparison + 4,
+            Num::GtS => comparison + 5,
+            Num::GtU => comparison + 6,
+            Num::GeS => comparison + 9,
+            Num::GeU => comparison + 10,
+            Num::Add => arithmetic,
+            Num::Sub => arithmetic + 1,
+            Num::Mul => arithmetic + 2,
+            Num::DivU => arithmetic + 4,
+            Num::RemS => arithmetic + 5,
+            Num::RemU => arithmetic + 6,
+            Num::ShrU => arithmetic + 12,
+        }
+    }
+}
+
+/// An instruction, where blocks are started by [`Instr::Block`], [`Instr::Loop`] or [`Instr::If`]
+/// and closed by [`Instr::End`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Instr {
+    Block,
+    Loop,
+    If,
+    Else,
+    End,
+    /// Branches to the block a number of blocks out from the innermost one
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(Func),
+    Drop,
+    Select,
+    LocalGet(Local),
+    LocalSet(Local),
+    LocalTee(Local),
+    GlobalGet(Global),
+    GlobalSet(Global),
+    I32Const(i32),
+    I64Const(i64),
+    /// Loads a value of a type from a number of bytes at an address plus an offset, zero extended
+    Load(Type, u8, u32),
+    /// Stores the lowest bytes of a value of a type at an address plus an offset
+    Store(Type, u8, u32),
+    MemorySize,
+    MemoryGrow,
+    MemoryCopy,
+    MemoryFill,
+    I32(Num),
+    I64(Num),
+    I32WrapI64,
+    I64ExtendI32U,
+}
+
+impl Instr {
+    /// The name of the instruction in the text format, with its immediate operands
+    fn text(self, module: &Module, function: &Function) -> String {
+        match self {
+            Instr::Block => "block".to_string(),
+            Instr::Loop => "loop".to_string(),
+            Instr::If => "if".to_string(),
+            Instr::Else => "else".to_string(),
+            Instr::End => "end".to_string(),
+            Instr::Br(depth) => format!("br {depth}"),
+            Instr::BrIf(depth) => format!("br_if {depth}"),
+            Instr::Return => "return".to_string(),
+            Instr::Call(func) => format!("call ${}", module.functions[func.0 as usize].name),
+            Instr::Drop => "drop".to_string(),
+            Instr::Select => "select".to_string(),
+            Instr::LocalGet(local) => format!("local.get ${}", function.local_name(local)),
+            Instr::LocalSet(local) => format!("local.set ${}", function.local_name(local)),
+            Instr::LocalTee(local) => format!("local.tee ${}", function.local_name(local)),
+            Instr::GlobalGet(global) => {
+                format!("global.get ${}", module.globals[global.0 as usize].name)
+            }
+            Instr::GlobalSet(global) => {
+                format!("global.set ${}", module.globals[global.0 as usize].name)
+            }
+            Instr::I32Const(value) => format!("i32.const {value}"),
+            Instr::I64Const(value) => format!("i64.const {value}"),
+            Instr::Load(ty, bytes, offset) => {
+                let width = match (ty, bytes) {
+                    (Type::I32, 4) | (Type::I64, 8) => String::new(),
+                    (_, bytes) => format!("{}_u", bytes * 8),
+                };
+                format!("{}.load{width}{}", ty.name(), offset_text(offset))
+            }
+            Instr::Store(ty, bytes, offset) => {
+                let width = match (ty, bytes) {
+                    (Type::I32, 4) | (Type::I64, 8) => String::new(),
+                    (_, bytes) => (bytes * 8).to_string(),
+                };
+                format!("{}.store{width}{}", ty.name(), offset_text(offset))
+            }
+            Instr::MemorySize => "memory.size".to_string(),
+            Instr::MemoryGrow => "memory.grow".to_string(),
+            Instr::MemoryCopy => "memory.copy".to_string(),
+            Instr::MemoryFill => "memory.fill".to_string(),
+            Instr::I32(num) => format!("i32.{}", num.name()),
+            Instr::I64(num) => format!("i64.{}", num.name()),
+            Instr::I32WrapI64 => "i32.wrap_i64".to_string(),
+            Instr::I64ExtendI32U => "i64.extend_i32_u".to_string(),
+        }
+    }
+
+    fn encode(self, code: &mut Vec<u8>) {
+        // The type of blocks without parameters and results
+        const EMPTY: u8 = 0x40;
+
+        match self {
+            Instr::Block => code.extend_from_slice(&[0x02, EMPTY]),
+            Instr::Loop => code.extend_from_slice(&[0x03, EMPTY]),
+            Instr::If => code.extend_from_slice(&[0x04, EMPTY]),
+            Instr::Else => code.push(0x05),
+            Instr::End => code.push(0x0b),
+            Instr::Br(depth) => {
+                code.push(0x0c);
+                unsigned(code, depth.into());
+            }
+            Instr::BrIf(depth) => {
+                code.push(0x0d);
+                unsigned(code, depth.into());
+            }
+            Instr::Return => code.push(0x0f),
+            Instr::Call(func) => {
+                code.push(0x10);
+                unsigned(code, func.0.into());
+            }
+            Instr::Drop => code.push(0x1a),
+            Instr::Select => code.push(0x1b),
+            Instr::LocalGet(local) => {
+                code.push(0x20);
+                unsigned(code, local.0.into());
+            }
+            Instr::LocalSet(local) => {
+                code.push(0x21);
+                unsigned(code, local.0.into());
+            }
+            Instr::LocalTee(local) => {
+                code.push(0x22);
+                unsigned(code, local.0.into());
+            }
+            Instr::GlobalGet(global) => {
+                code.push(0x23);
+                unsigned(code, global.0.into());
+            }
+            Instr::GlobalSet(global) => {
+                code.push(0x24);
+                unsigned(code, global.0.into());
+            }
+            Instr::I32Const(value) => {
+                code.push(0x41);
+                signed(code, value.into());
+            }
+            Instr::I64Const(value) => {
+                code.push(0x42);
+                signed(code, value);
+            }
+            Instr::Load(ty, bytes, offset) => {
+                let opcode = match (ty, bytes) {
+                    (Type::I32, 1) => 0x2d,
+                    (Type::I32, 2) => 0x2f,
+                    (Type::I32, _) => 0x28,
+                    (Type::I64, 1) => 0x31,
+                    (Type::I64, 2) => 0x33,
+                    (Type::I64, 4) => 0x35,
+                    (Type::I64, _) => 0x29,
+                };
+                code.push(opcode);
+                memory_argument(code, bytes, offset);
+            }
+            Instr::Store(ty, bytes, offset) => {
+                let opcode = match (ty, bytes) {
+                    (Type::I32, 1) => 0x3a,
+                    (Type::I32, 2) => 0x3b,
+                    (Type::I32, _) => 0x36,
+                    (Type::I64, 1) => 0x3c,
+                    (Type::I64, 2) => 0x3d,
+                    (Type::I64, 4) => 0x3e,
+                    (Type::I64, _) => 0x37,
+                };
+                code.push(opcode);
+                memory_argument(code, bytes, offset);
+            }
+            Instr::MemorySize => code.extend_from_slice(&[0x3f, 0x00]),
+            Instr::MemoryGrow => code.extend_from_slice(&[0x40, 0x00]),
+            Instr::MemoryCopy => code.extend_from_slice(&[0xfc, 10, 0x00, 0x00]),
+            Instr::MemoryFill => code.extend_from_slice(&[0xfc, 11, 0x00]),
+            Instr::I32(num) => code.push(num.opcode(Type::I32)),
+            Instr::I64(num) => code.push(num.opcode(Type::I64)),
+            Instr::I32WrapI64 => code.push(0xa7),
+            Instr::I64ExtendI32U => code.push(0xad),
+        }
+    }
+}
+
+fn offset_text(offset: u32) -> String {
+    match offset {
+        0 => String::new(),
+        offset => format!(" offset={offset}"),
+    }
+}
+
+/// Encodes the alignment and the offset of a memory access, assuming it is naturally aligned
+fn memory_argument(code: &mut Vec<u8>, bytes: u8, offset: u32) {
+    unsigned(code, bytes.trailing_zeros().into());
+    unsigned(code, offset.into());
+}
+
+struct Function {
+    name: String,
+    params: Vec<(String, Type)>,
+    result: Option<Type>,
+    /// The module and the name it is imported from
+    import: Option<(String, String)>,
+    export: Option<String>,
+    locals: Vec<(String, Type)>,
+    body: Vec<Instr>,
+}
+
+impl Function {
+    fn local_name(&self, local: Local) -> &str {
+        let index = local.0 as usize;
+        match self.params.get(index) {
+            Some((name, _)) => name,
+            None => &self.locals[index - self.params.len()].0,
+        }
+    }
+
+    fn signature(&self) -> (Vec<Type>, Option<Type>) {
+        let params = self.params.iter().map(|&(_, ty)| ty).collect();
+        (params, self.result)
+    }
+}
+
+/// A mutable global variable
+struct GlobalVariable {
+    name: String,
+    ty: Type,
+    initial: i64,
+    export: Option<String>,
+}
+
+/// A module with a single memory exported as `memory`
+pub(crate) struct Module {
+    functions: Vec<Function>,
+    globals: Vec<GlobalVariable>,
+    /// The initial size of the memory in pages of 64 KiB
+    pages: u32,
+    data: Vec<(u32, Vec<u8>)>,
+}
+
+impl Module {
+    pub fn new(pages: u32) -> Self {
+        Self {
+            functions: vec![],
+            globals: vec![],
+            pages,
+            data: vec![],
+        }
+    }
+
+    /// Imports a function, which has to happen before any function is declared
+    pub fn import(
+        &mut self,
+        module: &str,
+        name: &str,
+        params: &[Type],
+        result: Option<Type>,
+    ) -> Func {
+        assert!(
+            self.functions.iter().all(|f| f.import.is_some()),
+            "functions are imported after functions were declared"
+        );
+
+        let params = params.iter().map(|&ty| (String::new(), ty)).collect();
+        self.add_function(name, params, result, Some((module.into(), name.into())))
+    }
+
+    /// Declares a function with named parameters, whose body is defined later
+    pub fn function(&mut self, name: &str, params: &[(&str, Type)], result: Option<Type>) -> Func {
+        let params = params.iter().map(|&(n, ty)| (n.into(), ty)).collect();
+        self.add_function(name, params, result, None)
+    }
+
+    fn add_function(
+        &mut self,
+        name: &str,
+        params: Vec<(String, Type)>,
+        result: Option<Type>,
+        import: Option<(String, String)>,
+    ) -> Func {
+        self.functions.push(Function {
+            name: name.into(),
+            params,
+            result,
+            import,
+            export: None,
+            locals: vec![],
+            body: vec![],
+        });
+        Func(self.functions.len() as u32 - 1)
+    }
+
+    pub fn export(&mut self, func: Func, name: &str) {
+        self.functions[func.0 as usize].export = Some(name.into());
+    }
+
+    /// The parameter of a function at an index
+    pub fn param(&self, func: Func, index: usize) -> Local {
+        assert!(index < self.functions[func.0 as usize].params.len());
+        Local(index as u32)
+    }
+
+    /// Adds a local variable to a function
+    pub fn local(&mut self, func: Func, name: &str, ty: Type) -> Local {
+        let function = &mut self.functions[func.0 as usize];
+        function.locals.push((name.into(), ty));
+        Local((function.params.len() + function.locals.len()) as u32 - 1)
+    }
+
+    /// Sets the body of a function, without the `end` that closes it
+    pub fn define(&mut self, func: Func, body: Vec<Instr>) {
+        self.functions[func.0 as usize].body = body;
+    }
+
+    pub fn global(&mut self, name: &str, ty: Type, initial: i64, export: Option<&str>) -> Global {
+        self.globals.push(GlobalVariable {
+            name: name.into(),
+            ty,
+            initial,
+            export: export.map(Into::into),
+        });
+        Global(self.globals.len() as u32 - 1)
+    }
+
+    /// Initializes memory at an address with bytes
+    pub fn data(&mut self, address: u32, bytes: Vec<u8>) {
+        self.data.push((address, bytes));
+    }
+
+    /// Writes the module in the text format
+    pub fn text(&self) -> String {
+        let mut text = "(module\n".to_string();
+
+        for function in &self.functions {
+            let Some((module, name)) = &function.import else {
+                continue;
+            };
+            let _ = writeln!(
+                text,
+                "  (import \"{module}\" \"{name}\" (func ${}{}))",
+                function.name,
+                signature_text(function, false)
+            );
+        }
+
+        let _ = writeln!(text, "  (memory (export \"memory\") {})", self.pages);
+
+        for global in &self.globals {
+            let export = match &global.export {
+                Some(name) => format!(" (export \"{name}\")"),
+                None => String::new(),
+            };
+            let ty = global.ty.name();
+            let _ = writeln!(
+                text,
+                "  (global ${}{export} (mut {ty}) ({ty}.const {}))",
+                global.name, global.initial
+            );
+        }
+
+        for function in self.functions.iter().filter(|f| f.import.is_none()) {
+            let export = match &function.export {
+                Some(name) => format!(" (export \"{name}\")"),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                text,
+                "\n  (func ${}{export}{}",
+                function.name,
+                signature_text(function, true)
+            );
+            for (name, ty) in &function.locals {
+                let _ = writeln!(text, "    (local ${name} {})", ty.name());
+            }
+
+            let mut depth = 2;
+            for &instr in &function.body {
+                if matches!(instr, Instr::End | Instr::Else) {
+                    depth -= 1;
+                }
+                let indent = "  ".repeat(depth);
+                let _ = writeln!(text, "{indent}{}", instr.text(self, function));
+                if matches!(instr, Instr::Block | Instr::Loop | Instr::If | Instr::Else) {
+                    depth += 1;
+                }
+            }
+            text.push_str("  )\n");
+        }
+
+        for (address, bytes) in &self.data {
+            let _ = write!(text, "\n  (data (i32.const {address}) \"");
+            for &byte in bytes {
+                match byte {
+                    b'"' | b'\\' => {
+                        let _ = write!(text, "\\{}", byte as char);
+                    }
+                    b' '..=b'~' => text.push(byte as char),
+                    _ => {
+                        let _ = write!(text, "\\{byte:02x}");
+                    }
+                }
+            }
+            text.push_str("\")\n");
+        }
+
+        text.push_str(")\n");
+        text
+    }
+
+    /// Encodes the module in the binary format
+    pub fn binary(&self) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut types: Vec<(Vec<Type>, Option<Type>)> = vec![];
+        let mut type_indices = vec![];
+        for function in &self.functions {
+            let signature = function.signature();
+            let index = match types.iter().position(|t| *t == signature) {
+                Some(index) => index,
+                None => {
+                    types.push(signature);
+                    types.len() - 1
+                }
+            };
+            type_indices.push(index);
+        }
+
+        let mut section = vec![];
+        unsigned(&mut section, types.len() as u64);
+        for (params, result) in &types {
+            section.push(0x60);
+            unsigned(&mut section, params.len() as u64);
+            section.extend(params.iter().map(|ty| ty.code()));
+            unsigned(&mut section, result.iter().len() as u64);
+            section.extend(result.iter().map(|ty| ty.code()));
+        }
+        push_section(&mut module, 1, &section);
+
+        let imports: Vec<_> = self.functions.iter().zip(&type_indices).collect();
+        let (imports, defined): (Vec<_>, Vec<_>) =
+            imports.into_iter().partition(|(f, _)| f.import.is_some());
+
+        let mut section = vec![];
+        unsigned(&mut section, imports.len() as u64);
+        for (function, &index) in &imports {
+            let (module, name) = function.import.as_ref().unwrap();
+            name_bytes(&mut section, module);
+            name_bytes(&mut section, name);
+            section.push(0x00);
+            unsigned(&mut section, index as u64);
+        }
+        push_section(&mut module, 2, &section);
+
+        let mut section = vec![];
+        unsigned(&mut section, defined.len() as u64);
+        for (_, &index) in &defined {
+            unsigned(&mut section, index as u64);
+        }
+        push_section(&mut module, 3, &section);
+
+        let mut section = vec![1, 0x00];
+        unsigned(&mut section, self.pages.into());
+        push_section(&mut module, 5, &section);
+
+        let mut section = vec![];
+        unsigned(&mut section, self.globals.len() as u64);
+        for global in &self.globals {
+            section.extend_from_slice(&[global.ty.code(), 0x01]);
+            let initial = match global.ty {
+                Type::I32 => Instr::I32Const(global.initial as i32),
+                Type::I64 => Instr::I64Const(global.initial),
+            };
+            initial.encode(&mut section);
+            Instr::End.encode(&mut section);
+        }
+        push_section(&mut module, 6, &section);
+
+        let mut exports = vec![("memory", 0x02, 0)];
+        for (index, function) in self.functions.iter().enumerate() {
+            if let Some(name) = &function.export {
+                exports.push((name, 0x00, index));
+            }
+        }
+        for (index, global) in self.globals.iter().enumerate() {
+            if let Some(name) = &global.export {
+                exports.push((name, 0x03, index));
+            }
+        }
+        let mut section = vec![];
+        unsigned(&mut section, exports.len() as u64);
+        for (name, kind, index) in exports {
+            name_bytes(&mut section, name);
+            section.push(kind);
+            unsigned(&mut section, index as u64);
+        }
+        push_section(&mut module, 7, &section);
+
+        let mut section = vec![];
+        unsigned(&mut section, defined.len() as u64);
+        for (function, _) in &defined {
+            let mut code = vec![];
+            unsigned(&mut code, function.locals.len() as u64);
+            for (_, ty) in &function.locals {
+                code.extend_from_slice(&[1, ty.code()]);
+            }
+            for instr in &function.body {
+                instr.encode(&mut code);
+            }
+            Instr::End.encode(&mut code);
+
+            unsigned(&mut section, code.len() as u64);
+            section.extend_from_slice(&code);
+        }
+        push_section(&mut module, 10, &section);
+
+        let mut section = vec![];
+        unsigned(&mut section, self.data.len() as u64);
+        for (address, bytes) in &self.data {
+            section.push(0x00);
+            Instr::I32Const(*address as i32).encode(&mut section);
+            Instr::End.encode(&mut section);
+            unsigned(&mut section, bytes.len() as u64);
+            section.extend_from_slice(bytes);
+        }
+        push_section(&mut module, 11, &section);
+
+        module
+    }
+}
+
+/// The parameters and the result of a function in the text format, with the names of the
+/// parameters if `named`
+fn signature_text(function: &Function, named: bool) -> String {
+    let mut text = String::new();
+    for (name, ty) in &function.params {
+        match named {
+            true => {
+                let _ = write!(text, " (param ${name} {})", ty.name());
+            }
+            false => {
+                let _ = write!(text, " (param {})", ty.name());
+            }
+        }
+    }
+    if let Some(ty) = function.result {
+        let _ = write!(text, " (result {})", ty.name());
+    }
+
+    text
+}
+
+fn push_section(module: &mut Vec<u8>, id: u8, section: &[u8]) {
+    module.push(id);
+    unsigned(module, section.len() as u64);
+    module.extend_from_slice(section);
+}
+
+fn name_bytes(code: &mut Vec<u8>, name: &str) {
+    unsigned(code, name.len() as u64);
+    code.extend_from_slice(name.as_bytes());
+}
+
+/// Encodes an unsigned LEB128 number
+fn unsigned(code: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            code.push(byte);
+            return;
+        }
+        code.push(byte | 0x80);
+    }
+}
+
+/// Encodes a signed LEB128 number
+fn signed(code: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            code.push(byte);
+            return;
+        }
+        code.push(byte | 0x80);
+    }
+}
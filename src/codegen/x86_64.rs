@@ -0,0 +1,1288 @@
+//! Compiles brainfuck to a standalone x86-64 Linux executable
+
+use std::{collections::BTreeMap, mem};
+
+use super::{
+    assembler::{Alu, Assembler, Cond, Label, Mem, Reg, Size},
+    Unfolded,
+};
+use crate::{
+    cell::Cell,
+    config::{Boundary, Config, Eof, Overflow},
+    lexer::Position,
+    optimizer::Op,
+    program::Program,
+};
+
+/// The address the executable is loaded at
+const BASE_ADDRESS: u64 = 0x40_0000;
+const PAGE_SIZE: u64 = 0x1000;
+/// The size of the ELF header and the two program headers in front of the code
+const HEADER_SIZE: u64 = 64 + 2 * 56;
+
+/// The size of the output and input buffers
+const BUFFER_SIZE: i32 = 4096;
+
+/// Offsets of the variables in the zero initialized data segment
+const OUTPUT_BUFFER: i64 = 0;
+const INPUT_BUFFER: i64 = BUFFER_SIZE as i64;
+const OUTPUT_LENGTH: i64 = 2 * BUFFER_SIZE as i64;
+const INPUT_POSITION: i64 = OUTPUT_LENGTH + 8;
+const INPUT_LENGTH: i64 = INPUT_POSITION + 8;
+const DATA_SIZE: u64 = INPUT_LENGTH as u64 + 8;
+
+const SYS_READ: i64 = 0;
+const SYS_WRITE: i64 = 1;
+const SYS_MMAP: i64 = 9;
+const SYS_RT_SIGACTION: i64 = 13;
+const SYS_MREMAP: i64 = 25;
+const SYS_EXIT_GROUP: i64 = 231;
+
+const SIGPIPE: i64 = 13;
+/// `PROT_READ | PROT_WRITE`
+const PROT_READ_WRITE: i64 = 3;
+/// `MAP_PRIVATE | MAP_ANONYMOUS`
+const MAP_PRIVATE_ANONYMOUS: i64 = 0x22;
+const MREMAP_MAYMOVE: i64 = 1;
+/// System calls return errors as values from -4095 to -1
+const MAX_ERRNO: i32 = 4095;
+
+/// The register holding the address of the tape
+const TAPE: Reg = Reg::Rbx;
+/// The register holding the index of the current cell
+const POINTER: Reg = Reg::R12;
+/// The register holding the amount of cells on the tape
+const LENGTH: Reg = Reg::R13;
+/// The register holding the address of the context of a function
+#[cfg(feature = "jit")]
+const CONTEXT: Reg = Reg::R14;
+/// The register holding the stack pointer at the start of a function
+#[cfg(feature = "jit")]
+const STACK: Reg = Reg::R15;
+/// The registers a function uses that its caller expects to be kept
+#[cfg(feature = "jit")]
+const SAVED: [Reg; 5] = [TAPE, POINTER, LENGTH, CONTEXT, STACK];
+
+/// The offsets of the fields of the context of a function: the address of the tape, its length,
+/// the data pointer and the index of the bytecode instruction that failed
+#[cfg(feature = "jit")]
+pub(crate) const CONTEXT_TAPE: i32 = 0;
+#[cfg(feature = "jit")]
+pub(crate) const CONTEXT_LENGTH: i32 = 8;
+#[cfg(feature = "jit")]
+pub(crate) const CONTEXT_POINTER: i32 = 16;
+#[cfg(feature = "jit")]
+pub(crate) const CONTEXT_INSTRUCTION: i32 = 24;
+
+/// The values a function returns
+#[cfg(feature = "jit")]
+pub(crate) mod exit {
+    pub const FINISHED: u64 = 0;
+    /// A callback failed and recorded why
+    pub const CALLBACK_FAILED: u64 = 1;
+    pub const PAST_START: u64 = 2;
+    pub const PAST_END: u64 = 3;
+    pub const OVERFLOW: u64 = 4;
+    pub const UNDERFLOW: u64 = 5;
+}
+
+/// Compiles a program to a statically linked x86-64 Linux executable for cells of type `C`,
+/// optimized for the configuration
+///
+/// The executable has no dependencies and only makes system calls. Like `bfi`, it reports errors
+/// on stderr and exits with code 3 if the program fails while running, or with code 4 if reading
+/// the input or writing the output fails. `#` is not supported and compiles to nothing.
+///
+/// # Panics
+///
+/// Panics if the data pointer starts at the largest index or the tape does not fit into memory.
+pub fn compile<C: Cell>(program: &Program, config: &Config) -> Vec<u8> {
+    let unfolded = Unfolded::new(program, config);
+    let size = Size::of_bits(C::BITS);
+    let mut compiler = Compiler::new(config, size, Mode::Executable, Some(&unfolded));
+    compiler.executable_entry(program.optimized_for(config).ops());
+    compiler.executable_runtime();
+    compiler.cold_code();
+    compiler.strings();
+
+    // The data segment starts at the page after the code
+    let code_address = BASE_ADDRESS + HEADER_SIZE;
+    let data_address = (code_address + compiler.asm.len() as u64).next_multiple_of(PAGE_SIZE);
+    let data = (data_address - code_address) as i64;
+    let Variables {
+        output_buffer,
+        input_buffer,
+        output_length,
+        input_position,
+        input_length,
+    } = compiler.variables;
+    compiler.asm.bind_at(output_buffer, data + OUTPUT_BUFFER);
+    compiler.asm.bind_at(input_buffer, data + INPUT_BUFFER);
+    compiler.asm.bind_at(output_length, data + OUTPUT_LENGTH);
+    compiler.asm.bind_at(input_position, data + INPUT_POSITION);
+    compiler.asm.bind_at(input_length, data + INPUT_LENGTH);
+
+    elf(&compiler.asm.finish(), data_address)
+}
+
+/// Addresses of the `extern "sysv64"` functions that a [compiled function](compile_function) calls
+/// back into, which all take the pointer to its context as their first argument
+#[cfg(feature = "jit")]
+#[cfg_attr(
+    not(all(target_arch = "x86_64", target_os = "linux")),
+    allow(dead_code)
+)]
+pub(crate) struct Callbacks {
+    /// `fn(context, byte) -> u64` writes a byte, returning 0 if that failed
+    pub output: usize,
+    /// `fn(context) -> i64` reads a byte, returning -1 at the end of the input or -2 if reading
+    /// failed
+    pub input: usize,
+    /// `fn(context, pointer) -> u64` prints the cells around the data pointer, returning 0 if that
+    /// failed
+    pub dump: usize,
+    /// `fn(context, index, pointer) -> usize` grows the tape to include an index outside of it,
+    /// returning the index on the grown tape after updating the tape, its length and the data
+    /// pointer in the context
+    pub grow: usize,
+}
+
+/// Compiles optimized operations to a function for cells of type `C` that runs them on the tape in
+/// its context, `extern "sysv64" fn(context: *mut Context) -> u64`
+///
+/// The function returns one of the [`exit`] values after writing the data pointer and the index of
+/// the bytecode instruction that failed to the context. The code does not depend on its address.
+#[cfg(feature = "jit")]
+#[cfg_attr(
+    not(all(target_arch = "x86_64", target_os = "linux")),
+    allow(dead_code)
+)]
+pub(crate) fn compile_function<C: Cell>(
+    ops: &[(Op, Position)],
+    config: &Config,
+    callbacks: &Callbacks,
+) -> Vec<u8> {
+    let mut compiler = Compiler::new(config, Size::of_bits(C::BITS), Mode::Function, None);
+    compiler.function_entry(ops);
+    compiler.function_runtime(callbacks);
+    compiler.cold_code();
+    compiler.asm.finish()
+}
+
+/// Wraps machine code that starts with its entry point into an ELF executable
+fn elf(code: &[u8], data_address: u64) -> Vec<u8> {
+    let file_size = HEADER_SIZE + code.len() as u64;
+    let mut elf = Vec::with_capacity(file_size as usize);
+
+    // ELF header: a little endian 64-bit executable for x86-64
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    elf.extend_from_slice(&2u16.to_le_bytes());
+    elf.extend_from_slice(&0x3eu16.to_le_bytes());
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&(BASE_ADDRESS + HEADER_SIZE).to_le_bytes());
+    elf.extend_from_slice(&64u64.to_le_bytes());
+    elf.extend_from_slice(&0u64.to_le_bytes());
+    elf.extend_from_slice(&0u32.to_le_bytes());
+    elf.extend_from_slice(&64u16.to_le_bytes());
+    elf.extend_from_slice(&56u16.to_le_bytes());
+    elf.extend_from_slice(&2u16.to_le_bytes());
+    elf.extend_from_slice(&64u16.to_le_bytes());
+    elf.extend_from_slice(&0u16.to_le_bytes());
+    elf.extend_from_slice(&0u16.to_le_bytes());
+
+    // The whole file is loaded as readable and executable, followed by the writable variables
+    program_header(&mut elf, 0b101, BASE_ADDRESS, file_size, file_size);
+    program_header(&mut elf, 0b110, data_address, 0, DATA_SIZE);
+
+    elf.extend_from_slice(code);
+    elf
+}
+
+/// Appends a program header of a segment that is loaded from the start of the file
+fn program_header(elf: &mut Vec<u8>, flags: u32, address: u64, file_size: u64, memory_size: u64) {
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&flags.to_le_bytes());
+    elf.extend_from_slice(&0u64.to_le_bytes());
+    elf.extend_from_slice(&address.to_le_bytes());
+    elf.extend_from_slice(&address.to_le_bytes());
+    elf.extend_from_slice(&file_size.to_le_bytes());
+    elf.extend_from_slice(&memory_size.to_le_bytes());
+    elf.extend_from_slice(&PAGE_SIZE.to_le_bytes());
+}
+
+/// What the machine code is compiled into
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// A standalone executable that makes system calls
+    Executable,
+    /// A function that calls back into its host
+    #[cfg(feature = "jit")]
+    Function,
+}
+
+/// Where an operation that can fail is, passed to the routines in `rsi`
+#[derive(Clone, Copy)]
+enum Site {
+    /// The label of the ` at <line>:<column>` of error messages of an executable
+    Location(Label),
+    /// The index of the bytecode instruction of the operation, for functions
+    #[cfg(feature = "jit")]
+    Instruction(usize),
+}
+
+/// Labels of the routines the compiled program calls
+#[derive(Clone, Copy)]
+struct Routines {
+    /// Writes the byte in `al`
+    output: Label,
+    /// Reads a byte into `rax`, or -1 at the end of the input
+    input: Label,
+    /// Prints the cells around the data pointer
+    #[cfg(feature = "jit")]
+    dump: Label,
+    /// Returns from a function with the value in `rax`
+    #[cfg(feature = "jit")]
+    exit: Label,
+    /// Writes the buffered output
+    flush: Label,
+    /// Writes the buffered output, failing if that does not work
+    flush_checked: Label,
+    /// Writes `rdx` bytes at `rsi` to the file descriptor `rdi`
+    write_all: Label,
+    /// Reports the message at `rdi` and exits with the code in `rdx`
+    fail: Label,
+    /// Fails for the index in `rax` outside of the tape
+    outside: Label,
+    /// Wraps the index in `rax` around the tape
+    wrap: Label,
+    /// Grows the tape to include the index in `rax`, which is adjusted if cells are added at the
+    /// start
+    grow: Label,
+    overflow: Label,
+    underflow: Label,
+}
+
+/// Labels of the strings of error messages, stored with their length in front
+#[derive(Clone, Copy)]
+struct Messages {
+    error: Label,
+    past_start: Label,
+    past_end: Label,
+    overflow: Label,
+    underflow: Label,
+    input: Label,
+    output: Label,
+    grow: Label,
+    allocate: Label,
+    newline: Label,
+}
+
+/// Labels of the variables in the data segment
+#[derive(Clone, Copy)]
+struct Variables {
+    output_buffer: Label,
+    input_buffer: Label,
+    output_length: Label,
+    input_position: Label,
+    input_length: Label,
+}
+
+/// An amount that was added to or subtracted from a cell
+#[derive(Clone, Copy)]
+enum Amount {
+    Immediate(u64),
+    Register(Reg),
+}
+
+/// Code that rarely runs, which is emitted after the program to keep loops compact
+enum Cold<'a> {
+    /// The index in `rax` lies outside of the tape
+    Outside {
+        label: Label,
+        back: Label,
+        site: Site,
+        replay: Option<&'a [(Op, Position)]>,
+    },
+    /// A cell overflowed, or underflowed if the amount was negative
+    Overflow {
+        label: Label,
+        back: Label,
+        site: Site,
+        replay: Option<&'a [(Op, Position)]>,
+        negative: bool,
+        cell: Mem,
+        /// The operation that already changed the cell, undone before trapping so the cell keeps
+        /// its value from before the overflow
+        applied: Option<(Alu, Amount)>,
+    },
+}
+
+struct Compiler<'a> {
+    asm: Assembler,
+    config: &'a Config,
+    size: Size,
+    mode: Mode,
+    /// The unoptimized operations of an executable, which report the exact instruction that failed
+    unfolded: Option<&'a Unfolded>,
+    /// The instructions the operation being compiled was optimized from, if it can fail and there
+    /// are several
+    replay: Option<&'a [(Op, Position)]>,
+    /// The index of the bytecode instruction of the operation being compiled
+    instruction: usize,
+    routines: Routines,
+    messages: Messages,
+    variables: Variables,
+    /// The ` at <line>:<column>` at the end of error messages from each position
+    locations: BTreeMap<(usize, usize), Label>,
+    cold: Vec<Cold<'a>>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(config: &'a Config, size: Size, mode: Mode, unfolded: Option<&'a Unfolded>) -> Self {
+        let mut asm = Assembler::new();
+
+        Self {
+            routines: Routines {
+                output: asm.label(),
+                input: asm.label(),
+                #[cfg(feature = "jit")]
+                dump: asm.label(),
+                #[cfg(feature = "jit")]
+                exit: asm.label(),
+                flush: asm.label(),
+                flush_checked: asm.label(),
+                write_all: asm.label(),
+                fail: asm.label(),
+                outside: asm.label(),
+                wrap: asm.label(),
+                grow: asm.label(),
+                overflow: asm.label(),
+                underflow: asm.label(),
+            },
+            messages: Messages {
+                error: asm.label(),
+                past_start: asm.label(),
+                past_end: asm.label(),
+                overflow: asm.label(),
+                underflow: asm.label(),
+                input: asm.label(),
+                output: asm.label(),
+                grow: asm.label(),
+                allocate: asm.label(),
+                newline: asm.label(),
+            },
+            variables: Variables {
+                output_buffer: asm.label(),
+                input_buffer: asm.label(),
+                output_length: asm.label(),
+                input_position: asm.label(),
+                input_length: asm.label(),
+            },
+            asm,
+            config,
+            size,
+            mode,
+            unfolded,
+            replay: None,
+            instruction: 0,
+            locations: BTreeMap::new(),
+            cold: vec![],
+        }
+    }
+
+    /// The current cell
+    fn cell(&self) -> Mem {
+        Mem::indexed(TAPE, POINTER, self.size.bytes())
+    }
+
+    /// The largest value of a cell
+    fn max(&self) -> u64 {
+        u64::MAX >> (64 - 8 * u32::from(self.size.bytes()))
+    }
+
+    /// The label of the location of an error at a position
+    fn location(&mut self, position: Position) -> Label {
+        let key = (position.line, position.column);
+        match self.locations.get(&key) {
+            Some(label) => *label,
+            None => {
+                let label = self.asm.label();
+                self.locations.insert(key, label);
+                label
+            }
+        }
+    }
+
+    /// Where the operation being compiled is, at a position in the source code
+    fn site(&mut self, position: Position) -> Site {
+        match self.mode {
+            Mode::Executable => Site::Location(self.location(position)),
+            #[cfg(feature = "jit")]
+            Mode::Function => Site::Instruction(self.instruction),
+        }
+    }
+
+    /// Passes a site to a routine in `rsi`
+    fn load_site(&mut self, site: Site) {
+        match site {
+            Site::Location(label) => self.asm.lea(Reg::Rsi, Mem::Rip(label)),
+            #[cfg(feature = "jit")]
+            Site::Instruction(index) => self.asm.mov_imm(Reg::Rsi, index as i64),
+        }
+    }
+
+    /// Emits the entry point of an executable, which sets up the tape, runs the program and exits
+    fn executable_entry(&mut self, ops: &[(Op, Position)]) {
+        let config = self.config;
+        let size = config
+            .start
+            .checked_add(1)
+            .expect("data pointer starts past the largest tape")
+            .max(config.tape_size);
+        let bytes = size
+            .checked_mul(usize::from(self.size.bytes()))
+            .filter(|&bytes| bytes <= isize::MAX as usize)
+            .expect("the tape does not fit into memory");
+
+        // Ignore SIGPIPE, so writing to a closed pipe fails like in `bfi` instead of killing the
+        // program
+        let ignore = self.asm.label();
+        self.asm.mov_imm(Reg::Rax, SYS_RT_SIGACTION);
+        self.asm.mov_imm(Reg::Rdi, SIGPIPE);
+        self.asm.lea(Reg::Rsi, Mem::Rip(ignore));
+        self.asm.mov_imm(Reg::Rdx, 0);
+        self.asm.mov_imm(Reg::R10, 8);
+        self.asm.syscall();
+
+        let allocate = self.asm.label();
+        self.asm.mov_imm(Reg::Rax, SYS_MMAP);
+        self.asm.mov_imm(Reg::Rdi, 0);
+        self.asm.mov_imm(Reg::Rsi, bytes as i64);
+        self.asm.mov_imm(Reg::Rdx, PROT_READ_WRITE);
+        self.asm.mov_imm(Reg::R10, MAP_PRIVATE_ANONYMOUS);
+        self.asm.mov_imm(Reg::R8, -1);
+        self.asm.mov_imm(Reg::R9, 0);
+        self.asm.syscall();
+        self.asm.alu_imm(Alu::Cmp, Reg::Rax, -MAX_ERRNO);
+        self.asm.jump_if(Cond::AboveOrEqual, allocate);
+
+        self.asm.mov(TAPE, Reg::Rax);
+        self.asm.mov_imm(POINTER, config.start as i64);
+        self.asm.mov_imm(LENGTH, size as i64);
+
+        self.block(ops);
+
+        // Errors at the end of the program are attributed to its last operation
+        let last = match ops.last() {
+            Some((Op::Loop(_, end), _)) => *end,
+            Some((_, position)) => *position,
+            None => Position::default(),
+        };
+        let location = self.location(last);
+        self.asm.lea(Reg::Rsi, Mem::Rip(location));
+        self.asm.call(self.routines.flush_checked);
+        self.asm.mov_imm(Reg::Rax, SYS_EXIT_GROUP);
+        self.asm.mov_imm(Reg::Rdi, 0);
+        self.asm.syscall();
+
+        self.asm.bind(allocate);
+        self.asm.lea(Reg::Rdi, Mem::Rip(self.messages.allocate));
+        self.asm.lea(Reg::Rsi, Mem::Rip(self.messages.newline));
+        self.asm.mov_imm(Reg::Rdx, 3);
+        self.asm.jmp(self.routines.fail);
+
+        // The `struct sigaction` with `SIG_IGN` as the handler
+        self.asm.bind(ignore);
+        self.asm.bytes(&1u64.to_le_bytes());
+        self.asm.bytes(&[0; 24]);
+    }
+
+    /// Emits the entry point of a function, which loads the tape from its context, runs the program
+    /// and returns
+    #[cfg(feature = "jit")]
+    fn function_entry(&mut self, ops: &[(Op, Position)]) {
+        for reg in SAVED {
+            self.asm.push(reg);
+        }
+        self.asm.mov(STACK, Reg::Rsp);
+        self.asm.mov(CONTEXT, Reg::Rdi);
+        load_context(&mut self.asm);
+
+        self.block(ops);
+        self.asm.mov_imm(Reg::Rax, exit::FINISHED as i64);
+
+        // Returns the value in `rax` from anywhere in the program, with the site in `rsi`
+        self.asm.bind(self.routines.exit);
+        self.asm.store(
+            Size::Qword,
+            Mem::base(CONTEXT, CONTEXT_INSTRUCTION),
+            Reg::Rsi,
+        );
+        self.asm
+            .store(Size::Qword, Mem::base(CONTEXT, CONTEXT_POINTER), POINTER);
+        self.asm.mov(Reg::Rsp, STACK);
+        for reg in SAVED.into_iter().rev() {
+            self.asm.pop(reg);
+        }
+        self.asm.ret();
+    }
+
+    /// Compiles operations with an explicit stack of the loops they are nested in, so deeply nested
+    /// loops cannot overflow the stack
+    fn block(&mut self, ops: &[(Op, Position)]) {
+        let size = self.size;
+        let mut rest = ops.iter();
+
+        // The labels of each loop that is currently open, with the operations after it
+        let mut stack = vec![];
+
+        loop {
+            let Some((op, position)) = rest.next() else {
+                let Some((outer, start, end)) = stack.pop() else {
+                    return;
+                };
+
+                self.asm.alu_mem_imm(Alu::Cmp, size, self.cell(), 0);
+                self.asm.jump_if(Cond::NotEqual, start);
+                self.asm.bind(end);
+                // The `]` of the loop
+                self.instruction += 1;
+                rest = outer;
+                continue;
+            };
+            let position = *position;
+            self.replay = self
+                .unfolded
+                .and_then(|unfolded| unfolded.get(op, position));
+
+            match *op {
+                Op::Add(amount) => self.add(amount, position),
+                Op::Move(offset) => {
+                    self.resolve(offset, position);
+                    self.asm.mov(POINTER, Reg::Rax);
+                }
+                Op::Output => {
+                    let site = self.site(position);
+                    self.asm.load(Size::Byte, Reg::Rax, self.cell());
+                    self.load_site(site);
+                    self.asm.call(self.routines.output);
+                }
+                Op::Input => {
+                    let site = self.site(position);
+                    self.load_site(site);
+                    self.asm.call(self.routines.input);
+
+                    let store = self.asm.label();
+                    match self.config.eof {
+                        Eof::Unchanged => {
+                            let skip = self.asm.label();
+                            self.asm.test(Reg::Rax, Reg::Rax);
+                            self.asm.jump_if(Cond::Sign, skip);
+                            self.asm.store(size, self.cell(), Reg::Rax);
+                            self.asm.bind(skip);
+                        }
+                        Eof::Zero => {
+                            self.asm.test(Reg::Rax, Reg::Rax);
+                            self.asm.jump_if(Cond::NotSign, store);
+                            self.asm.alu(Alu::Xor, Reg::Rax, Reg::Rax);
+                        }
+                        // Storing -1 sets every bit of the cell
+                        Eof::MinusOne => (),
+                    }
+
+                    self.asm.bind(store);
+                    if self.config.eof != Eof::Unchanged {
+                        self.asm.store(size, self.cell(), Reg::Rax);
+                    }
+                }
+                Op::Dump => match self.mode {
+                    // Executables do not support dumping the tape
+                    Mode::Executable => (),
+                    #[cfg(feature = "jit")]
+                    Mode::Function => {
+                        let site = self.site(position);
+                        self.load_site(site);
+                        self.asm.call(self.routines.dump);
+                    }
+                },
+                Op::Clear => self.asm.store_imm(size, self.cell(), 0),
+                Op::MulAdd { offset, factor } => self.mul_add(offset, factor, position),
+                Op::Scan(stride) => {
+                    let start = self.asm.label();
+                    let end = self.asm.label();
+
+                    self.asm.bind(start);
+                    self.asm.alu_mem_imm(Alu::Cmp, size, self.cell(), 0);
+                    self.asm.jump_if(Cond::Equal, end);
+                    self.resolve(stride, position);
+                    self.asm.mov(POINTER, Reg::Rax);
+                    self.asm.jmp(start);
+                    self.asm.bind(end);
+                }
+                Op::Loop(ref body, _) => {
+                    let start = self.asm.label();
+                    let end = self.asm.label();
+
+                    self.asm.alu_mem_imm(Alu::Cmp, size, self.cell(), 0);
+                    self.asm.jump_if(Cond::Equal, end);
+                    self.asm.bind(start);
+                    // The body follows the `[` in the bytecode
+                    self.instruction += 1;
+                    stack.push((mem::replace(&mut rest, body.iter()), start, end));
+                    continue;
+                }
+            }
+
+            // Every other operation is a single bytecode instruction
+            self.instruction += 1;
+        }
+    }
+
+    /// Adds an amount to the current cell
+    fn add(&mut self, amount: i64, position: Position) {
+        let size = self.size;
+        let cell = self.cell();
+
+        if self.config.overflow == Overflow::Wrap {
+            // Truncating the amount keeps it the same modulo the width of the cell
+            match i32::try_from(amount) {
+                Err(_) if size == Size::Qword => {
+                    self.asm.mov_imm(Reg::Rcx, amount);
+                    self.asm.alu_mem(Alu::Add, size, cell, Reg::Rcx);
+                }
+                _ => self.asm.alu_mem_imm(Alu::Add, size, cell, amount),
+            }
+            return;
+        }
+
+        let negative = amount < 0;
+        let magnitude = amount.unsigned_abs();
+        let op = if negative { Alu::Sub } else { Alu::Add };
+
+        if magnitude > self.max() {
+            let (overflowed, back) = self.overflow(position, negative, cell, None);
+            self.asm.jmp(overflowed);
+            self.asm.bind(back);
+        } else {
+            let applied = Some((op, Amount::Immediate(magnitude)));
+            let (overflowed, back) = self.overflow(position, negative, cell, applied);
+            self.alu_cell(op, cell, magnitude);
+            // The carry flag is set if an unsigned addition or subtraction overflows
+            self.asm.jump_if(Cond::Below, overflowed);
+            self.asm.bind(back);
+        }
+    }
+
+    /// Adds a magnitude to or subtracts it from a cell
+    fn alu_cell(&mut self, op: Alu, cell: Mem, magnitude: u64) {
+        match i32::try_from(magnitude) {
+            Err(_) if self.size == Size::Qword => {
+                self.asm.mov_imm(Reg::Rcx, magnitude as i64);
+                self.asm.alu_mem(op, self.size, cell, Reg::Rcx);
+            }
+            _ => self.asm.alu_mem_imm(op, self.size, cell, magnitude as i64),
+        }
+    }
+
+    /// Adds the current cell multiplied by a factor to the cell at an offset
+    fn mul_add(&mut self, offset: isize, factor: i64, position: Position) {
+        let size = self.size;
+        let skip = self.asm.label();
+
+        self.asm.alu_mem_imm(Alu::Cmp, size, self.cell(), 0);
+        self.asm.jump_if(Cond::Equal, skip);
+        self.resolve(offset, position);
+        self.asm.load(size, Reg::Rcx, self.cell());
+
+        if self.config.overflow == Overflow::Wrap {
+            let target = Mem::indexed(TAPE, Reg::Rax, size.bytes());
+
+            // The lower bits of a product do not depend on the higher bits of its factors
+            match (factor, i32::try_from(factor)) {
+                (1, _) => self.asm.alu_mem(Alu::Add, size, target, Reg::Rcx),
+                (-1, _) => self.asm.alu_mem(Alu::Sub, size, target, Reg::Rcx),
+                (_, Ok(factor)) => {
+                    self.asm.imul_imm(Reg::Rcx, Reg::Rcx, factor);
+                    self.asm.alu_mem(Alu::Add, size, target, Reg::Rcx);
+                }
+                (_, Err(_)) => {
+                    self.asm.mov_imm(Reg::Rdx, factor);
+                    self.asm.imul(Reg::Rcx, Reg::Rdx);
+                    self.asm.alu_mem(Alu::Add, size, target, Reg::Rcx);
+                }
+            }
+        } else {
+            // `mul` needs `rax`, so the index of the target cell moves to `rdi`
+            self.asm.mov(Reg::Rdi, Reg::Rax);
+            let target = Mem::indexed(TAPE, Reg::Rdi, size.bytes());
+            let negative = factor < 0;
+            let magnitude = factor.unsigned_abs();
+            let mut backs = vec![];
+
+            let product = match magnitude {
+                1 => Reg::Rcx,
+                _ => {
+                    let (overflowed, back) = self.overflow(position, negative, target, None);
+                    backs.push(back);
+                    self.asm.mov_imm(Reg::Rax, magnitude as i64);
+                    self.asm.mul(Reg::Rcx);
+                    self.asm.test(Reg::Rdx, Reg::Rdx);
+                    self.asm.jump_if(Cond::NotEqual, overflowed);
+
+                    if size != Size::Qword {
+                        self.asm.mov(Reg::Rdx, Reg::Rax);
+                        self.asm.shr(Reg::Rdx, 8 * size.bytes());
+                        self.asm.jump_if(Cond::NotEqual, overflowed);
+                    }
+                    Reg::Rax
+                }
+            };
+
+            let op = if negative { Alu::Sub } else { Alu::Add };
+            let applied = Some((op, Amount::Register(product)));
+            let (overflowed, back) = self.overflow(position, negative, target, applied);
+            backs.push(back);
+            self.asm.alu_mem(op, size, target, product);
+            self.asm.jump_if(Cond::Below, overflowed);
+            for back in backs {
+                self.asm.bind(back);
+            }
+        }
+
+        self.asm.bind(skip);
+    }
+
+    /// Computes the index of the cell `offset` cells away from the data pointer into `rax`,
+    /// handling indices outside of the tape like the boundary behavior says
+    fn resolve(&mut self, offset: isize, position: Position) {
+        match i32::try_from(offset) {
+            Ok(offset) => self.asm.lea(Reg::Rax, Mem::base(POINTER, offset)),
+            Err(_) => {
+                self.asm.mov_imm(Reg::Rax, offset as i64);
+                self.asm.alu(Alu::Add, Reg::Rax, POINTER);
+            }
+        }
+
+        let label = self.asm.label();
+        let back = self.asm.label();
+
+        // Negative indices are larger than any length when compared without sign
+        self.asm.alu(Alu::Cmp, Reg::Rax, LENGTH);
+        self.asm.jump_if(Cond::AboveOrEqual, label);
+        self.asm.bind(back);
+
+        let site = self.site(position);
+        self.cold.push(Cold::Outside {
+            label,
+            back,
+            site,
+            replay: self.replay,
+        });
+    }
+
+    /// Creates the labels of the code that handles an overflow of a cell, and of the code to
+    /// continue at after a saturated cell
+    fn overflow(
+        &mut self,
+        position: Position,
+        negative: bool,
+        cell: Mem,
+        applied: Option<(Alu, Amount)>,
+    ) -> (Label, Label) {
+        let label = self.asm.label();
+        let back = self.asm.label();
+        let site = self.site(position);
+
+        self.cold.push(Cold::Overflow {
+            label,
+            back,
+            site,
+            replay: self.replay,
+            negative,
+            cell,
+            applied,
+        });
+
+        (label, back)
+    }
+
+    /// Emits the cold code, including the code of the operations replayed by it
+    fn cold_code(&mut self) {
+        while let Some(cold) = self.cold.pop() {
+            match cold {
+                Cold::Outside {
+                    label,
+                    back,
+                    site,
+                    replay,
+                } => {
+                    self.asm.bind(label);
+
+                    match self.config.boundary {
+                        Boundary::Error => {
+                            self.replay(replay);
+                            self.load_site(site);
+                            self.asm.jmp(self.routines.outside);
+                        }
+                        Boundary::Wrap => {
+                            self.asm.call(self.routines.wrap);
+                            self.asm.jmp(back);
+                        }
+                        Boundary::Grow => {
+                            self.load_site(site);
+                            self.asm.call(self.routines.grow);
+                            self.asm.jmp(back);
+                        }
+                    }
+                }
+                Cold::Overflow {
+                    label,
+                    back,
+                    site,
+                    replay,
+                    negative,
+                    cell,
+                    applied,
+                } => {
+                    self.asm.bind(label);
+
+                    if self.config.overflow == Overflow::Saturate {
+                        let value = if negative { 0 } else { -1 };
+                        self.asm.store_imm(self.size, cell, value);
+                        self.asm.jmp(back);
+                    } else {
+                        if let Some((op, amount)) = applied {
+                            let undo = if op == Alu::Add { Alu::Sub } else { Alu::Add };
+                            match amount {
+                                Amount::Immediate(magnitude) => {
+                                    self.alu_cell(undo, cell, magnitude)
+                                }
+                                Amount::Register(reg) => {
+                                    self.asm.alu_mem(undo, self.size, cell, reg)
+                                }
+                            }
+                        }
+
+                        self.replay(replay);
+                        self.load_site(site);
+                        let routine = match negative {
+                            true => self.routines.underflow,
+                            false => self.routines.overflow,
+                        };
+                        self.asm.jmp(routine);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the instructions a failed operation was optimized from one by one, so the one that
+    /// fails reports its error, keeping `rax` for the operation to fail with otherwise
+    fn replay(&mut self, replay: Option<&'a [(Op, Position)]>) {
+        if let Some(ops) = replay {
+            self.asm.push(Reg::Rax);
+            self.block(ops);
+            self.asm.pop(Reg::Rax);
+        }
+    }
+
+    /// Emits the routines an executable calls, which expect the location of the calling operation
+    /// in `rsi` if they can fail
+    fn executable_runtime(&mut self) {
+        let Routines {
+            output,
+            input,
+            flush,
+            flush_checked,
+            write_all,
+            fail,
+            outside,
+            wrap,
+            grow,
+            overflow,
+            underflow,
+            ..
+        } = self.routines;
+        let Variables {
+            output_buffer,
+            input_buffer,
+            output_length,
+            input_position,
+            input_length,
+        } = self.variables;
+        let asm = &mut self.asm;
+
+        asm.bind(output);
+        asm.load(Size::Qword, Reg::Rcx, Mem::Rip(output_length));
+        asm.lea(Reg::Rdx, Mem::Rip(output_buffer));
+        asm.store(Size::Byte, Mem::indexed(Reg::Rdx, Reg::Rcx, 1), Reg::Rax);
+        asm.alu_imm(Alu::Add, Reg::Rcx, 1);
+        asm.store(Size::Qword, Mem::Rip(output_length), Reg::Rcx);
+        asm.alu_imm(Alu::Cmp, Reg::Rcx, BUFFER_SIZE);
+        asm.jump_if(Cond::AboveOrEqual, flush_checked);
+        asm.ret();
+
+        let output_failed = asm.label();
+        asm.bind(flush_checked);
+        asm.push(Reg::Rsi);
+        asm.call(flush);
+        asm.pop(Reg::Rsi);
+        asm.test(Reg::Rax, Reg::Rax);
+        asm.jump_if(Cond::Sign, output_failed);
+        asm.ret();
+        asm.bind(output_failed);
+        asm.lea(Reg::Rdi, Mem::Rip(self.messages.output));
+        asm.mov_imm(Reg::Rdx, 4);
+        asm.jmp(fail);
+
+        // Returns 0 in `rax` if the output was written, or a negative value if not
+        asm.bind(flush);
+        asm.load(Size::Qword, Reg::Rdx, Mem::Rip(output_length));
+        asm.alu(Alu::Xor, Reg::Rax, Reg::Rax);
+        asm.store(Size::Qword, Mem::Rip(output_length), Reg::Rax);
+        asm.lea(Reg::Rsi, Mem::Rip(output_buffer));
+        asm.mov_imm(Reg::Rdi, 1);
+
+        // Falls through from `flush`, returning like it
+        let (repeat, written, failed) = (asm.label(), asm.label(), asm.label());
+        asm.bind(write_all);
+        asm.bind(repeat);
+        asm.test(Reg::Rdx, Reg::Rdx);
+        asm.jump_if(Cond::Equal, written);
+        asm.mov_imm(Reg::Rax, SYS_WRITE);
+        asm.syscall();
+        asm.test(Reg::Rax, Reg::Rax);
+        asm.jump_if(Cond::LessOrEqual, failed);
+        asm.alu(Alu::Add, Reg::Rsi, Reg::Rax);
+        asm.alu(Alu::Sub, Reg::Rdx, Reg::Rax);
+        asm.jmp(repeat);
+        asm.bind(written);
+        asm.alu(Alu::Xor, Reg::Rax, Reg::Rax);
+        asm.ret();
+        asm.bind(failed);
+        asm.mov_imm(Reg::Rax, -1);
+        asm.ret();
+
+        let (buffered, end_of_input, input_failed) = (asm.label(), asm.label(), asm.label());
+        asm.bind(input);
+        // Flush first, so prompts are visible before waiting for input
+        asm.call(flush_checked);
+        asm.load(Size::Qword, Reg::Rcx, Mem::Rip(input_position));
+        asm.load(Size::Qword, Reg::Rdx, Mem::Rip(input_length));
+        asm.alu(Alu::Cmp, Reg::Rcx, Reg::Rdx);
+        asm.jump_if(Cond::Below, buffered);
+        asm.push(Reg::Rsi);
+        asm.mov_imm(Reg::Rax, SYS_READ);
+        asm.mov_imm(Reg::Rdi, 0);
+        asm.lea(Reg::Rsi, Mem::Rip(input_buffer));
+        asm.mov_imm(Reg::Rdx, BUFFER_SIZE.into());
+        asm.syscall();
+        asm.pop(Reg::Rsi);
+        asm.test(Reg::Rax, Reg::Rax);
+        asm.jump_if(Cond::Sign, input_failed);
+        asm.jump_if(Cond::Equal, end_of_input);
+        asm.store(Size::Qword, Mem::Rip(input_length), Reg::Rax);
+        asm.mov_imm(Reg::Rcx, 0);
+        asm.bind(buffered);
+        asm.lea(Reg::Rdx, Mem::Rip(input_buffer));
+        asm.load(Size::Byte, Reg::Rax, Mem::indexed(Reg::Rdx, Reg::Rcx, 1));
+        asm.alu_imm(Alu::Add, Reg::Rcx, 1);
+        asm.store(Size::Qword, Mem::Rip(input_position), Reg::Rcx);
+        asm.ret();
+        asm.bind(end_of_input);
+        asm.mov_imm(Reg::Rax, -1);
+        asm.ret();
+        asm.bind(input_failed);
+        asm.lea(Reg::Rdi, Mem::Rip(self.messages.input));
+        asm.mov_imm(Reg::Rdx, 4);
+        asm.jmp(fail);
+
+        // Never returns, so the registers of the program can be used
+        let print = asm.label();
+        asm.bind(fail);
+        asm.mov(Reg::R14, Reg::Rdi);
+        asm.mov(Reg::R15, Reg::Rsi);
+        asm.push(Reg::Rdx);
+        asm.call(flush);
+        asm.lea(Reg::Rsi, Mem::Rip(self.messages.error));
+        asm.call(print);
+        asm.mov(Reg::Rsi, Reg::R14);
+        asm.call(print);
+        asm.mov(Reg::Rsi, Reg::R15);
+        asm.call(print);
+        asm.pop(Reg::Rdi);
+        asm.mov_imm(Reg::Rax, SYS_EXIT_GROUP);
+        asm.syscall();
+
+        // Prints the string at `rsi` to stderr
+        asm.bind(print);
+        asm.load(Size::Dword, Reg::Rdx, Mem::base(Reg::Rsi, 0));
+        asm.alu_imm(Alu::Add, Reg::Rsi, 4);
+        asm.mov_imm(Reg::Rdi, 2);
+        asm.jmp(write_all);
+
+        for (routine, message) in [
+            (overflow, self.messages.overflow),
+            (underflow, self.messages.underflow),
+        ] {
+            asm.bind(routine);
+            asm.lea(Reg::Rdi, Mem::Rip(message));
+            asm.mov_imm(Reg::Rdx, 3);
+            asm.jmp(fail);
+        }
+
+        // Converts a number of cells in a register to a number of bytes
+        let scale = self.size.bytes();
+        let to_bytes = |asm: &mut Assembler, reg| {
+            if scale > 1 {
+                asm.shl(reg, scale.trailing_zeros() as u8);
+            }
+        };
+
+        match self.config.boundary {
+            Boundary::Error => {
+                let past_end = asm.label();
+                asm.bind(outside);
+                asm.lea(Reg::Rdi, Mem::Rip(self.messages.past_end));
+                asm.test(Reg::Rax, Reg::Rax);
+                asm.jump_if(Cond::NotSign, past_end);
+                asm.lea(Reg::Rdi, Mem::Rip(self.messages.past_start));
+                asm.bind(past_end);
+                asm.mov_imm(Reg::Rdx, 3);
+                asm.jmp(fail);
+            }
+            Boundary::Wrap => wrap_routine(asm, wrap),
+            Boundary::Grow => {
+                let (remap, at_start, grow_failed) = (asm.label(), asm.label(), asm.label());
+
+                // Grows the end of the tape to at least twice its length
+                asm.bind(grow);
+                asm.test(Reg::Rax, Reg::Rax);
+                asm.jump_if(Cond::Sign, at_start);
+                asm.mov(Reg::Rcx, Reg::Rax);
+                asm.alu_imm(Alu::Add, Reg::Rcx, 1);
+                asm.mov(Reg::Rdx, LENGTH);
+                asm.alu(Alu::Add, Reg::Rdx, LENGTH);
+                asm.alu(Alu::Cmp, Reg::Rcx, Reg::Rdx);
+                asm.cmov_below(Reg::Rcx, Reg::Rdx);
+                asm.push(Reg::Rax);
+                asm.call(remap);
+                asm.mov(LENGTH, Reg::Rcx);
+                asm.pop(Reg::Rax);
+                asm.ret();
+
+                // Adds at least as many cells as the tape has in front of it, moving its contents
+                asm.bind(at_start);
+                asm.mov(Reg::Rcx, Reg::Rax);
+                asm.neg(Reg::Rcx);
+                asm.alu(Alu::Cmp, Reg::Rcx, LENGTH);
+                asm.cmov_below(Reg::Rcx, LENGTH);
+                asm.push(Reg::Rax);
+                asm.push(Reg::Rcx);
+                asm.alu(Alu::Add, Reg::Rcx, LENGTH);
+                asm.call(remap);
+                asm.mov(Reg::R8, Reg::Rcx);
+                asm.pop(Reg::Rdx);
+                let last = |index| Mem::Sib {
+                    base: TAPE,
+                    index: Some(index),
+                    scale,
+                    disp: -1,
+                };
+                asm.lea(Reg::Rsi, last(LENGTH));
+                asm.lea(Reg::Rdi, last(Reg::R8));
+                asm.mov(Reg::Rcx, LENGTH);
+                to_bytes(asm, Reg::Rcx);
+                // `std; rep movsb; cld` copies backwards, since the ranges overlap
+                asm.bytes(&[0xfd, 0xf3, 0xa4, 0xfc]);
+                asm.mov(Reg::Rdi, TAPE);
+                asm.mov(Reg::Rcx, Reg::Rdx);
+                to_bytes(asm, Reg::Rcx);
+                asm.alu(Alu::Xor, Reg::Rax, Reg::Rax);
+                // `rep stosb`
+                asm.bytes(&[0xf3, 0xaa]);
+                asm.mov(LENGTH, Reg::R8);
+                asm.alu(Alu::Add, POINTER, Reg::Rdx);
+                asm.pop(Reg::Rax);
+                asm.alu(Alu::Add, Reg::Rax, Reg::Rdx);
+                asm.ret();
+
+                // Resizes the tape to the length in `rcx`, keeping `rcx` and `rsi`
+                asm.bind(remap);
+                asm.push(Reg::Rsi);
+                asm.push(Reg::Rcx);
+                asm.mov(Reg::Rdi, TAPE);
+                asm.mov(Reg::Rsi, LENGTH);
+                to_bytes(asm, Reg::Rsi);
+                asm.mov(Reg::Rdx, Reg::Rcx);
+                to_bytes(asm, Reg::Rdx);
+                asm.mov_imm(Reg::R10, MREMAP_MAYMOVE);
+                asm.mov_imm(Reg::Rax, SYS_MREMAP);
+                asm.syscall();
+                asm.pop(Reg::Rcx);
+                asm.pop(Reg::Rsi);
+                asm.alu_imm(Alu::Cmp, Reg::Rax, -MAX_ERRNO);
+                asm.jump_if(Cond::AboveOrEqual, grow_failed);
+                asm.mov(TAPE, Reg::Rax);
+                asm.ret();
+                asm.bind(grow_failed);
+                asm.lea(Reg::Rdi, Mem::Rip(self.messages.grow));
+                asm.mov_imm(Reg::Rdx, 3);
+                asm.jmp(fail);
+            }
+        }
+    }
+
+    /// Emits the routines a function calls, which expect the index of the calling bytecode
+    /// instruction in `rsi` if they can fail
+    #[cfg(feature = "jit")]
+    fn function_runtime(&mut self, callbacks: &Callbacks) {
+        let Routines {
+            output,
+            input,
+            dump,
+            exit,
+            outside,
+            wrap,
+            grow,
+            overflow,
+            underflow,
+            ..
+        } = self.routines;
+        let asm = &mut self.asm;
+
+        // Calls a callback with the arguments after the context in `rsi` and `rdx`. The site of the
+        // caller is pushed before, which also aligns the stack to 16 bytes for the call.
+        let callback = |asm: &mut Assembler, address: usize| {
+            asm.mov(Reg::Rdi, CONTEXT);
+            asm.mov_imm(Reg::Rax, address as i64);
+            asm.call_reg(Reg::Rax);
+            asm.pop(Reg::Rsi);
+        };
+        let failed = asm.label();
+
+        asm.bind(output);
+        asm.push(Reg::Rsi);
+        asm.mov(Reg::Rsi, Reg::Rax);
+        callback(asm, callbacks.output);
+        asm.test(Reg::Rax, Reg::Rax);
+        asm.jump_if(Cond::Equal, failed);
+        asm.ret();
+
+        asm.bind(input);
+        asm.push(Reg::Rsi);
+        callback(asm, callbacks.input);
+        asm.alu_imm(Alu::Cmp, Reg::Rax, -2);
+        asm.jump_if(Cond::Equal, failed);
+        asm.ret();
+
+        asm.bind(dump);
+        asm.push(Reg::Rsi);
+        asm.mov(Reg::Rsi, POINTER);
+        callback(asm, callbacks.dump);
+        asm.test(Reg::Rax, Reg::Rax);
+        asm.jump_if(Cond::Equal, failed);
+        asm.ret();
+
+        for (label, value) in [
+            (failed, exit::CALLBACK_FAILED),
+            (overflow, exit::OVERFLOW),
+            (underflow, exit::UNDERFLOW),
+        ] {
+            asm.bind(label);
+            asm.mov_imm(Reg::Rax, value as i64);
+            asm.jmp(exit);
+        }
+
+        match self.config.boundary {
+            Boundary::Error => {
+                let past_start = asm.label();
+                asm.bind(outside);
+                asm.test(Reg::Rax, Reg::Rax);
+                asm.jump_if(Cond::Sign, past_start);
+                asm.mov_imm(Reg::Rax, exit::PAST_END as i64);
+                asm.jmp(exit);
+                asm.bind(past_start);
+                asm.mov_imm(Reg::Rax, exit::PAST_START as i64);
+                asm.jmp(exit);
+            }
+            Boundary::Wrap => wrap_routine(asm, wrap),
+            Boundary::Grow => {
+                // The callback grows the tape of the machine, which may move it
+                asm.bind(grow);
+                asm.push(Reg::Rsi);
+                asm.mov(Reg::Rsi, Reg::Rax);
+                asm.mov(Reg::Rdx, POINTER);
+                callback(asm, callbacks.grow);
+                load_context(asm);
+                asm.ret();
+            }
+        }
+    }
+
+    /// Emits the strings of error messages
+    fn strings(&mut self) {
+        let Messages {
+            error,
+            past_start,
+            past_end,
+            overflow,
+            underflow,
+            input,
+            output,
+            grow,
+            allocate,
+            newline,
+        } = self.messages;
+
+        let mut strings = vec![
+            (error, "error: ".to_string()),
+            (
+                past_start,
+                "data pointer moved past the start of the tape".to_string(),
+            ),
+            (
+                past_end,
+                "data pointer moved past the end of the tape".to_string(),
+            ),
+            (overflow, "cell overflowed".to_string()),
+            (underflow, "cell underflowed".to_string()),
+            (input, "failed to read input".to_string()),
+            (output, "failed to write output".to_string()),
+            (grow, "failed to grow the tape".to_string()),
+            (allocate, "failed to allocate the tape".to_string()),
+            (newline, "\n".to_string()),
+        ];
+        for (&(line, column), &label) in &self.locations {
+            strings.push((label, format!(" at {line}:{column}\n")));
+        }
+
+        for (label, string) in strings {
+            self.asm.bind(label);
+            self.asm.bytes(&(string.len() as u32).to_le_bytes());
+            self.asm.bytes(string.as_bytes());
+        }
+    }
+}
+
+/// Emits the routine that wraps the index in `rax` around the tape
+fn wrap_routine(asm: &mut Assembler, wrap: Label) {
+    let positive = asm.label();
+    asm.bind(wrap);
+    asm.cqo();
+    asm.idiv(LENGTH);
+    asm.test(Reg::Rdx, Reg::Rdx);
+    asm.jump_if(Cond::NotSign, positive);
+    asm.alu(Alu::Add, Reg::Rdx, LENGTH);
+    asm.bind(positive);
+    asm.mov(Reg::Rax, Reg::Rdx);
+    asm.ret();
+}
+
+/// Loads the tape, its length and the data pointer from the context of a function
+#[cfg(feature = "jit")]
+fn load_context(asm: &mut Assembler) {
+    asm.load(Size::Qword, TAPE, Mem::base(CONTEXT, CONTEXT_TAPE));
+    asm.load(Size::Qword, LENGTH, Mem::base(CONTEXT, CONTEXT_LENGTH));
+    asm.load(Size::Qword, POINTER, Mem::base(CONTEXT, CONTEXT_POINTER));
+}
@@ -0,0 +1,66 @@
+/// The default amount of cells on the tape
+pub const DEFAULT_TAPE_SIZE: usize = 24576;
+
+/// What happens when the data pointer moves past either end of the tape
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// Stop the program with a [`RuntimeError`](crate::RuntimeError)
+    #[default]
+    Error,
+    /// Continue at the other end of the tape
+    Wrap,
+    /// Add more cells to the tape
+    Grow,
+}
+
+/// What happens when a cell is incremented past its maximum or decremented past zero
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Continue at the other end of the range of the cell
+    #[default]
+    Wrap,
+    /// Stay at the maximum or zero
+    Saturate,
+    /// Stop the program with a [`RuntimeError`](crate::RuntimeError)
+    Trap,
+}
+
+/// What happens to the current cell when `,` is executed after the end of the input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Eof {
+    /// Keep the value of the cell
+    #[default]
+    Unchanged,
+    /// Set the cell to zero
+    Zero,
+    /// Set the cell to -1, which is the maximum value of the cell
+    MinusOne,
+}
+
+/// Settings for a [`Machine`](crate::Machine)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The amount of cells on the tape when the machine is created
+    pub tape_size: usize,
+    /// The index of the cell the data pointer starts at
+    pub start: usize,
+    /// What happens when the data pointer moves past either end of the tape
+    pub boundary: Boundary,
+    /// What happens when a cell is incremented past its maximum or decremented past zero
+    pub overflow: Overflow,
+    /// What happens to the current cell when `,` is executed after the end of the input
+    pub eof: Eof,
+}
+
+impl Default for Config {
+    /// A fixed tape of [`DEFAULT_TAPE_SIZE`] wrapping cells with the data pointer in the middle
+    fn default() -> Self {
+        Self {
+            tape_size: DEFAULT_TAPE_SIZE,
+            start: DEFAULT_TAPE_SIZE / 2,
+            boundary: Boundary::Error,
+            overflow: Overflow::Wrap,
+            eof: Eof::Unchanged,
+        }
+    }
+}
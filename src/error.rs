@@ -0,0 +1,227 @@
+use std::{fmt, io};
+
+use crate::lexer::Position;
+
+/// The reason a program could not be parsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A `]` was found without a `[` before it
+    UnmatchedLoopClose,
+    /// A `[` was never closed by a `]`
+    UnclosedLoop,
+}
+
+/// An error that occurred while parsing, pointing at the offending `[` or `]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub position: Position,
+}
+
+impl ParseError {
+    fn label(&self) -> &'static str {
+        match self.kind {
+            ParseErrorKind::UnmatchedLoopClose => "loop has no beginning",
+            ParseErrorKind::UnclosedLoop => "loop has no ending",
+        }
+    }
+
+    /// Renders the error together with the offending line of the source code, similar to rustc diagnostics
+    ///
+    /// `name` is used to refer to the source code, usually its path.
+    pub fn render(&self, name: &str, source: &str) -> String {
+        render(self, self.label(), self.position, name, source)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::UnmatchedLoopClose => {
+                write!(f, "loop ending at {} has no beginning", self.position)
+            }
+            ParseErrorKind::UnclosedLoop => {
+                write!(f, "loop that starts at {} has no ending", self.position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The reason a program stopped while running
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    /// The data pointer moved before the first cell of the tape
+    PointerPastStart,
+    /// The data pointer moved after the last cell of the tape
+    PointerPastEnd,
+    /// A cell was incremented past its maximum
+    CellOverflow,
+    /// A cell was decremented past zero
+    CellUnderflow,
+    /// Reading the input failed
+    Input(io::ErrorKind),
+    /// Writing the output failed
+    Output(io::ErrorKind),
+    /// Writing the trace of a [traced run](crate::Machine::run_traced) failed
+    Trace(io::ErrorKind),
+}
+
+/// An error that occurred while running, pointing at the instruction that caused it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub position: Position,
+}
+
+impl RuntimeError {
+    fn label(&self) -> &'static str {
+        match self.kind {
+            RuntimeErrorKind::PointerPastStart | RuntimeErrorKind::PointerPastEnd => {
+                "data pointer moved here"
+            }
+            RuntimeErrorKind::CellOverflow => "cell incremented here",
+            RuntimeErrorKind::CellUnderflow => "cell decremented here",
+            RuntimeErrorKind::Input(_) => "input read here",
+            RuntimeErrorKind::Output(_) => "output written here",
+            RuntimeErrorKind::Trace(_) => "traced here",
+        }
+    }
+
+    /// Renders the error together with the offending line of the source code, similar to rustc diagnostics
+    ///
+    /// `name` is used to refer to the source code, usually its path.
+    pub fn render(&self, name: &str, source: &str) -> String {
+        render(self, self.label(), self.position, name, source)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            RuntimeErrorKind::PointerPastStart => {
+                write!(
+                    f,
+                    "data pointer moved past the start of the tape at {}",
+                    self.position
+                )
+            }
+            RuntimeErrorKind::PointerPastEnd => {
+                write!(
+                    f,
+                    "data pointer moved past the end of the tape at {}",
+                    self.position
+                )
+            }
+            RuntimeErrorKind::CellOverflow => write!(f, "cell overflowed at {}", self.position),
+            RuntimeErrorKind::CellUnderflow => write!(f, "cell underflowed at {}", self.position),
+            RuntimeErrorKind::Input(kind) => {
+                write!(f, "failed to read input at {}: {kind}", self.position)
+            }
+            RuntimeErrorKind::Output(kind) => {
+                write!(f, "failed to write output at {}: {kind}", self.position)
+            }
+            RuntimeErrorKind::Trace(kind) => {
+                write!(f, "failed to write trace at {}: {kind}", self.position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+fn render(
+    error: &impl fmt::Display,
+    label: &str,
+    position: Position,
+    name: &str,
+    source: &str,
+) -> String {
+    format!("error: {error}\n{}", snippet(label, position, name, source))
+}
+
+/// Renders the line of the source code at a position with a labeled caret below it, like the
+/// snippets in rustc diagnostics
+///
+/// `name` is used to refer to the source code, usually its path.
+pub fn snippet(label: &str, position: Position, name: &str, source: &str) -> String {
+    let offset = position.offset.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i);
+    let line = source[line_start..line_end].trim_end_matches('\r');
+
+    // Tabs are kept so the caret lines up with the source line
+    let padding: String = source[line_start..offset]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let line_number = position.line.to_string();
+    let gutter = " ".repeat(line_number.len());
+
+    format!(
+        "{gutter}--> {name}:{position}\n\
+         {gutter} |\n\
+         {line_number} | {line}\n\
+         {gutter} | {padding}^ {label}\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    fn parse_error(source: &str) -> ParseError {
+        Program::parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn unmatched_loop_close_is_located_after_tabs_crlf_and_multibyte_characters() {
+        let error = parse_error("+\t+\r\n é]");
+
+        assert_eq!(error.kind, ParseErrorKind::UnmatchedLoopClose);
+        assert_eq!(
+            error.position,
+            Position {
+                offset: 8,
+                line: 2,
+                column: 3
+            }
+        );
+    }
+
+    #[test]
+    fn unclosed_loop_is_located_after_tabs_crlf_and_multibyte_characters() {
+        let error = parse_error("é\r\n\t[+");
+
+        assert_eq!(error.kind, ParseErrorKind::UnclosedLoop);
+        assert_eq!(
+            error.position,
+            Position {
+                offset: 5,
+                line: 2,
+                column: 2
+            }
+        );
+    }
+
+    #[test]
+    fn rendered_snippet_keeps_tabs_and_drops_carriage_returns() {
+        let source = "+\t]\r\n+";
+
+        assert_eq!(
+            parse_error(source).render("test.bf", source),
+            concat!(
+                "error: loop ending at 1:3 has no beginning\n",
+                " --> test.bf:1:3\n",
+                "  |\n",
+                "1 | +\t]\n",
+                "  |  \t^ loop has no beginning\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{lexer::Position, parser::Instruction};
+
+/// The indentation of each level of nested loops
+const INDENT: &str = "    ";
+
+/// Formats a tree of instructions as brainfuck source code without comments
+///
+/// Loops without nested loops are kept on a single line like `[->+<]`, other loops put their
+/// brackets on separate lines and indent their body.
+pub fn format(instructions: &[(Instruction, Position)]) -> String {
+    let mut output = String::new();
+    let mut line = String::new();
+    let mut rest = instructions.iter();
+
+    // The instructions after each loop that is currently open on its own lines, so nested loops do
+    // not need recursion
+    let mut stack = vec![];
+
+    loop {
+        let Some((instruction, _)) = rest.next() else {
+            flush_line(&INDENT.repeat(stack.len()), &mut line, &mut output);
+
+            let Some(outer) = stack.pop() else {
+                break;
+            };
+
+            output.push_str(&format!("{}]\n", INDENT.repeat(stack.len())));
+            rest = outer;
+            continue;
+        };
+
+        match instruction {
+            Instruction::Loop(body, _)
+                if body.iter().any(|(i, _)| matches!(i, Instruction::Loop(..))) =>
+            {
+                let indent = INDENT.repeat(stack.len());
+                flush_line(&indent, &mut line, &mut output);
+
+                output.push_str(&format!("{indent}[\n"));
+                stack.push(rest);
+                rest = body.iter();
+            }
+            instruction => write_inline(instruction, &mut line),
+        }
+    }
+
+    output
+}
+
+fn write_inline(instruction: &Instruction, line: &mut String) {
+    match instruction {
+        Instruction::IncrementPointer => line.push('>'),
+        Instruction::DecrementPointer => line.push('<'),
+        Instruction::Increment => line.push('+'),
+        Instruction::Decrement => line.push('-'),
+        Instruction::Output => line.push('.'),
+        Instruction::Input => line.push(','),
+        Instruction::Dump => line.push('#'),
+        Instruction::Loop(body, _) => {
+            line.push('[');
+            for (instruction, _) in body {
+                write_inline(instruction, line);
+            }
+            line.push(']');
+        }
+    }
+}
+
+fn flush_line(indent: &str, line: &mut String, output: &mut String) {
+    if !line.is_empty() {
+        output.push_str(indent);
+        output.push_str(line);
+        output.push('\n');
+        line.clear();
+    }
+}
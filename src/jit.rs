@@ -0,0 +1,251 @@
+//! Runs programs as machine code compiled just in time on x86-64 Linux
+//!
+//! The optimized operations of a program are compiled by the [x86-64 code
+//! generator](crate::codegen::x86_64) to a function in memory mapped as executable. The function
+//! works on the tape of a [`Machine`] directly and calls back into Rust for input, output, dumps
+//! and growing the tape.
+
+use std::{
+    io::{self, BufWriter, Read, Write},
+    mem::offset_of,
+};
+
+use crate::{
+    cell::Cell,
+    codegen::x86_64::{self, exit, Callbacks},
+    config::Config,
+    error::{RuntimeError, RuntimeErrorKind},
+    machine::Machine,
+    program::Program,
+};
+
+/// The state a compiled function works on, starting with the fields the machine code reads and
+/// writes
+#[repr(C)]
+struct Context<'a, C: Cell> {
+    tape: *mut C,
+    length: usize,
+    pointer: usize,
+    /// The index of the bytecode instruction that failed
+    instruction: usize,
+    machine: &'a mut Machine<C>,
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+    /// The reason a callback failed
+    error: Option<RuntimeErrorKind>,
+}
+
+const _: () = {
+    assert!(offset_of!(Context<u8>, tape) == x86_64::CONTEXT_TAPE as usize);
+    assert!(offset_of!(Context<u8>, length) == x86_64::CONTEXT_LENGTH as usize);
+    assert!(offset_of!(Context<u8>, pointer) == x86_64::CONTEXT_POINTER as usize);
+    assert!(offset_of!(Context<u8>, instruction) == x86_64::CONTEXT_INSTRUCTION as usize);
+};
+
+impl<C: Cell> Context<'_, C> {
+    /// Points the context at the tape of the machine, which moves when it grows
+    fn load_tape(&mut self) {
+        let tape = self.machine.tape_mut();
+        self.tape = tape.as_mut_ptr();
+        self.length = tape.len();
+        self.pointer = self.machine.data_pointer();
+    }
+
+    fn flush(&mut self) -> bool {
+        match self.output.flush() {
+            Ok(()) => true,
+            Err(e) => {
+                self.error = Some(RuntimeErrorKind::Output(e.kind()));
+                false
+            }
+        }
+    }
+}
+
+/// Runs a program like [`Machine::run_with`], compiling it to machine code first
+///
+/// The program is interpreted if the memory for the machine code cannot be mapped.
+pub(crate) fn run<C: Cell>(
+    machine: &mut Machine<C>,
+    config: &Config,
+    program: &Program,
+    mut input: impl Read,
+    output: impl Write,
+) -> Result<(), RuntimeError> {
+    let callbacks = Callbacks {
+        output: write_byte::<C> as *const () as usize,
+        input: read_byte::<C> as *const () as usize,
+        dump: dump::<C> as *const () as usize,
+        grow: grow::<C> as *const () as usize,
+    };
+    let code = x86_64::compile_function::<C>(program.ops(), config, &callbacks);
+    let Some(memory) = Executable::map(&code) else {
+        return machine.run_with(program, input, output);
+    };
+
+    let mut output = BufWriter::new(output);
+    let mut context = Context {
+        tape: std::ptr::null_mut(),
+        length: 0,
+        pointer: 0,
+        instruction: 0,
+        machine: &mut *machine,
+        input: &mut input,
+        output: &mut output,
+        error: None,
+    };
+    context.load_tape();
+    // The machine code indexes the tape without checking the pointer on entry
+    assert!(
+        context.pointer < context.length,
+        "data pointer outside the tape"
+    );
+
+    // SAFETY: The code was compiled for cells of type `C` and a context laid out like `Context`.
+    let function: extern "sysv64" fn(&mut Context<C>) -> u64 =
+        unsafe { std::mem::transmute(memory.address) };
+    let status = function(&mut context);
+
+    let Context {
+        pointer,
+        instruction,
+        error,
+        ..
+    } = context;
+    machine.set_data_pointer(pointer);
+
+    let (instruction, kind) = match status {
+        exit::FINISHED => match output.flush() {
+            Ok(()) => return Ok(()),
+            // Attribute the error to the last instruction, since the program has already ended
+            Err(e) => (
+                program.bytecode().len().saturating_sub(1),
+                RuntimeErrorKind::Output(e.kind()),
+            ),
+        },
+        exit::CALLBACK_FAILED => (
+            instruction,
+            error.expect("a failed callback sets the error"),
+        ),
+        exit::PAST_START => (instruction, RuntimeErrorKind::PointerPastStart),
+        exit::PAST_END => (instruction, RuntimeErrorKind::PointerPastEnd),
+        exit::OVERFLOW => (instruction, RuntimeErrorKind::CellOverflow),
+        exit::UNDERFLOW => (instruction, RuntimeErrorKind::CellUnderflow),
+        status => unreachable!("compiled function returned {status}"),
+    };
+
+    Err(machine.locate_error(program, instruction, kind))
+}
+
+extern "sysv64" fn write_byte<C: Cell>(context: &mut Context<C>, byte: u8) -> u64 {
+    match context.output.write_all(&[byte]) {
+        Ok(()) => 1,
+        Err(e) => {
+            context.error = Some(RuntimeErrorKind::Output(e.kind()));
+            0
+        }
+    }
+}
+
+extern "sysv64" fn read_byte<C: Cell>(context: &mut Context<C>) -> i64 {
+    // Flush first, so prompts are visible before waiting for input
+    if !context.flush() {
+        return -2;
+    }
+
+    let mut byte = [0];
+    match context.input.read_exact(&mut byte) {
+        Ok(()) => i64::from(byte[0]),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => -1,
+        Err(e) => {
+            context.error = Some(RuntimeErrorKind::Input(e.kind()));
+            -2
+        }
+    }
+}
+
+extern "sysv64" fn dump<C: Cell>(context: &mut Context<C>, pointer: usize) -> u64 {
+    // Flush first, so the dump shows up after the output that came before it
+    if !context.flush() {
+        return 0;
+    }
+
+    context.machine.set_data_pointer(pointer);
+    context.machine.print_dump();
+    1
+}
+
+extern "sysv64" fn grow<C: Cell>(context: &mut Context<C>, index: isize, pointer: usize) -> usize {
+    context.machine.set_data_pointer(pointer);
+    let index = context
+        .machine
+        .outside_index(index - pointer as isize)
+        .expect("a tape that grows has no boundary");
+    context.load_tape();
+
+    index
+}
+
+/// Memory holding machine code, which is unmapped when dropped
+struct Executable {
+    address: *mut u8,
+    length: usize,
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+const MAP_FAILED: *mut u8 = !0 as *mut u8;
+
+extern "C" {
+    fn mmap(
+        address: *mut u8,
+        length: usize,
+        prot: i32,
+        flags: i32,
+        fd: i32,
+        offset: i64,
+    ) -> *mut u8;
+    fn mprotect(address: *mut u8, length: usize, prot: i32) -> i32;
+    fn munmap(address: *mut u8, length: usize) -> i32;
+}
+
+impl Executable {
+    /// Copies machine code into memory that is mapped as executable and no longer writable
+    fn map(code: &[u8]) -> Option<Self> {
+        // SAFETY: A new anonymous mapping does not alias any memory, and its length is not zero.
+        unsafe {
+            let length = code.len().max(1);
+            let address = mmap(
+                std::ptr::null_mut(),
+                length,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if address == MAP_FAILED {
+                return None;
+            }
+
+            let memory = Self { address, length };
+            std::ptr::copy_nonoverlapping(code.as_ptr(), address, code.len());
+            if mprotect(address, length, PROT_READ | PROT_EXEC) != 0 {
+                return None;
+            }
+
+            Some(memory)
+        }
+    }
+}
+
+impl Drop for Executable {
+    fn drop(&mut self) {
+        // SAFETY: The memory was mapped by `Executable::map` and is no longer used.
+        unsafe {
+            munmap(self.address, self.length);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+/// A single brainfuck command as found in the source code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    /// `>`: Move the instruction pointer to the left (increment)
+    IncrementPointer,
+    /// `<`: Move the instruction pointer to the right (decrement)
+    DecrementPointer,
+    /// `+`: Increment the value of the current cell
+    Increment,
+    /// `-`: Decrement the value of the current cell
+    Decrement,
+    /// `.`: Output the value of the current cell
+    Output,
+    /// `,`: Replace the value of the current cell with input
+    Input,
+    /// `[`: Jump to the matching `]` instruction if the current value is zero
+    LoopOpen,
+    /// `]`: Jump to the matching `[` instruction if the current value is not zero
+    LoopClose,
+    /// `#`: Print the data pointer and the cells around it to stderr, only with the
+    /// [`dump`](Extensions::dump) extension
+    Dump,
+}
+
+impl TryFrom<char> for Token {
+    /// The error is `()` since the result should simply be ignored, since every character that is not a valid one is a comment in brainfuck.
+    type Error = ();
+
+    fn try_from(symbol: char) -> Result<Self, Self::Error> {
+        match symbol {
+            '>' => Ok(Token::IncrementPointer),
+            '<' => Ok(Token::DecrementPointer),
+            '+' => Ok(Token::Increment),
+            '-' => Ok(Token::Decrement),
+            '.' => Ok(Token::Output),
+            ',' => Ok(Token::Input),
+            '[' => Ok(Token::LoopOpen),
+            ']' => Ok(Token::LoopClose),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Opt-in additions to the brainfuck language, all disabled by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Extensions {
+    /// Whether `#` is a [`Token::Dump`] instead of a comment
+    pub dump: bool,
+}
+
+/// The location of a character in the source code
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    /// The byte offset from the start of the source code
+    pub offset: usize,
+    /// The line number, starting at 1
+    pub line: usize,
+    /// The column in characters, starting at 1
+    pub column: usize,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Turns brainfuck source code into a list of tokens and their positions, ignoring every comment character
+pub fn lexer(source: impl Into<String>) -> Vec<(Token, Position)> {
+    lexer_with(source, Extensions::default())
+}
+
+/// Like [`lexer`], but also turns the characters of enabled extensions into tokens
+pub fn lexer_with(source: impl Into<String>, extensions: Extensions) -> Vec<(Token, Position)> {
+    let mut tokens = vec![];
+
+    let mut line = 1;
+    let mut column = 1;
+
+    for (offset, symbol) in source.into().char_indices() {
+        let token = match symbol {
+            '#' if extensions.dump => Ok(Token::Dump),
+            symbol => symbol.try_into(),
+        };
+
+        // Every other character that is not a valid token is simply ignored
+        if let Ok(token) = token {
+            tokens.push((
+                token,
+                Position {
+                    offset,
+                    line,
+                    column,
+                },
+            ));
+        }
+
+        if symbol == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    tokens
+}
@@ -0,0 +1,59 @@
+//! `bfi` - Brainfuck Interpreter written in Rust
+//!
+//! The interpreter is split into a [`lexer`] turning source code into [`Token`]s, a [`parser`]
+//! turning tokens into a tree of [`Instruction`]s, an [`optimizer`] folding them into [`Op`]s, which
+//! are flattened into [`Bytecode`] and finally executed by a [`Machine`].
+//!
+//! ```no_run
+//! use bfi::{Machine, Program};
+//!
+//! let program = Program::parse("++++++++[>++++++++<-]>+.")?;
+//! let mut machine = Machine::new();
+//!
+//! machine.run(&program)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Instead of stdin and stdout, any reader and writer can be used for input and output:
+//!
+//! ```
+//! use bfi::{Config, Eof, Machine, Program};
+//!
+//! let program = Program::parse(",[.,]")?;
+//! let mut machine: Machine = Machine::with_config(Config {
+//!     eof: Eof::Zero,
+//!     ..Config::default()
+//! });
+//! let mut output = vec![];
+//!
+//! machine.run_with(&program, &b"echo"[..], &mut output)?;
+//! assert_eq!(output, b"echo");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod bytecode;
+pub mod cell;
+pub mod codegen;
+pub mod config;
+pub mod error;
+pub mod format;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+mod jit;
+pub mod lexer;
+pub mod machine;
+pub mod optimizer;
+pub mod parser;
+pub mod profile;
+pub mod program;
+
+pub use bytecode::{compile, Bytecode};
+pub use cell::Cell;
+pub use config::{Boundary, Config, Eof, Overflow};
+pub use error::{ParseError, ParseErrorKind, RuntimeError, RuntimeErrorKind};
+pub use format::format;
+pub use lexer::{lexer, lexer_with, Extensions, Position, Token};
+pub use machine::{Machine, Status};
+pub use optimizer::{optimize, translate, Op};
+pub use parser::{parser, Instruction};
+pub use profile::{LoopProfile, Profile, SpanProfile};
+pub use program::Program;
@@ -0,0 +1,598 @@
+use std::{
+    io::{self, BufWriter, Read, Write},
+    ops::Range,
+};
+
+use crate::{
+    bytecode::{compile, Bytecode},
+    cell::Cell,
+    config::{Boundary, Config, Eof, Overflow},
+    error::{RuntimeError, RuntimeErrorKind},
+    lexer::Position,
+    optimizer::translate,
+    profile::Profile,
+    program::Program,
+};
+
+/// The amount of cells printed on either side of the data pointer by `#`
+pub const DUMP_WINDOW: usize = 8;
+
+/// Whether a run ended because the program finished or because it was paused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The last instruction of the program has run
+    Finished,
+    /// The run was paused before the instruction the instruction pointer points at
+    Paused,
+}
+
+/// The state of a brainfuck machine: the tape of cells and the data pointer into it
+///
+/// The state is kept between runs, so multiple programs can be run on the same tape. The machine is
+/// generic over the [`Cell`] type, which determines the width of each cell and defaults to `u8`.
+#[derive(Clone, Debug)]
+pub struct Machine<C: Cell = u8> {
+    tape: Vec<C>,
+    data_pointer: usize,
+    boundary: Boundary,
+    overflow: Overflow,
+    eof: Eof,
+}
+
+impl Machine {
+    /// Creates a machine with 8-bit cells and the [default configuration](Config::default)
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+}
+
+impl<C: Cell> Machine<C> {
+    /// Creates a machine with the given configuration
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the data pointer is outside of a tape that cannot grow, or if the tape
+    /// cannot hold it.
+    pub fn with_config(config: Config) -> Self {
+        let mut tape = vec![C::default(); config.tape_size];
+
+        if config.start >= tape.len() {
+            assert!(
+                config.boundary == Boundary::Grow,
+                "data pointer starts at {} outside of the tape of {} cells",
+                config.start,
+                tape.len()
+            );
+            let size = config
+                .start
+                .checked_add(1)
+                .expect("data pointer starts past the largest tape");
+            tape.resize(size, C::default());
+        }
+
+        Self {
+            tape,
+            data_pointer: config.start,
+            boundary: config.boundary,
+            overflow: config.overflow,
+            eof: config.eof,
+        }
+    }
+
+    /// The cells of the tape
+    pub fn tape(&self) -> &[C] {
+        &self.tape
+    }
+
+    /// The cells of the tape, to be modified in place
+    pub fn tape_mut(&mut self) -> &mut [C] {
+        &mut self.tape
+    }
+
+    /// The index of the current cell on the tape
+    pub fn data_pointer(&self) -> usize {
+        self.data_pointer
+    }
+
+    /// Moves the data pointer to a cell on the tape
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is outside of the tape.
+    pub fn set_data_pointer(&mut self, index: usize) {
+        assert!(
+            index < self.tape.len(),
+            "data pointer moved to {index} outside of the tape of {} cells",
+            self.tape.len()
+        );
+        self.data_pointer = index;
+    }
+
+    /// Formats a range of cells as a table of their indices and values, marking the current cell
+    ///
+    /// Cells outside of the tape are left out.
+    pub fn dump(&self, cells: Range<usize>) -> String {
+        let cells = cells.start.min(self.tape.len())..cells.end.min(self.tape.len());
+
+        let mut indices = String::new();
+        let mut values = String::new();
+        let mut marker = String::new();
+
+        for index in cells {
+            let value = self.tape[index].to_string();
+            let width = index.to_string().len().max(value.len());
+            let mark = if index == self.data_pointer { "^" } else { "" };
+
+            indices.push_str(&format!("{index:>width$} "));
+            values.push_str(&format!("{value:>width$} "));
+            marker.push_str(&format!("{mark:>width$} "));
+        }
+
+        format!(
+            "{}\n{}\n{}",
+            indices.trim_end(),
+            values.trim_end(),
+            marker.trim_end()
+        )
+    }
+
+    /// Runs a program, reading from stdin and writing to stdout
+    ///
+    /// Returns an error if the program does something the machine is configured to disallow.
+    pub fn run(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        self.run_with(program, io::stdin().lock(), io::stdout().lock())
+    }
+
+    /// Runs a program, reading input from `input` and writing output to `output`
+    ///
+    /// Output is buffered and flushed before reading input and when the program ends. Input is read
+    /// byte by byte, so unbuffered readers should be wrapped in a [`BufReader`](io::BufReader). The
+    /// dumps of `#` are always printed to stderr.
+    ///
+    /// A program that is not [optimized for](Program::is_optimized_for) the machine is optimized
+    /// again before it runs.
+    pub fn run_with(
+        &mut self,
+        program: &Program,
+        mut input: impl Read,
+        output: impl Write,
+    ) -> Result<(), RuntimeError> {
+        let program = program.optimized_for(&self.config());
+        self.run_until(&program, &mut 0, &mut input, output, |_| false)
+            .map(|_| ())
+    }
+
+    /// Runs a program from the bytecode instruction at `instruction_pointer` until it finishes or
+    /// `pause` returns `true` for the index of the next instruction
+    ///
+    /// `pause` is only asked after an instruction has run, so a paused run can be resumed with the
+    /// same predicate. Afterwards the instruction pointer points at the next instruction to run, or
+    /// at the instruction that caused an error. Input and output behave like in [`Self::run_with`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the program is not [optimized for](Program::is_optimized_for) the machine, since
+    /// the instruction pointer indexes its bytecode.
+    pub fn run_until(
+        &mut self,
+        program: &Program,
+        instruction_pointer: &mut usize,
+        mut input: impl Read,
+        output: impl Write,
+        mut pause: impl FnMut(usize) -> bool,
+    ) -> Result<Status, RuntimeError> {
+        self.assert_optimized_for(program);
+        let mut output = BufWriter::new(output);
+        let length = program.bytecode().len();
+
+        self.run_bytecode(
+            program.bytecode(),
+            instruction_pointer,
+            &mut input,
+            &mut output,
+            |_, _, next| Ok(next < length && pause(next)),
+        )
+        .map_err(|kind| self.locate_error(program, *instruction_pointer, kind))
+    }
+
+    /// Runs a program like [`Self::run_with`], writing a trace of every executed instruction to
+    /// `trace`
+    ///
+    /// The trace is written as JSON lines, one object per instruction with its line and column in
+    /// the source code and the data pointer and value of the current cell after it ran, like
+    /// `{"line":1,"column":3,"pointer":12288,"value":2}`. Programs parsed with
+    /// [`Program::parse_unoptimized`] trace every single instruction of the source code.
+    pub fn run_traced(
+        &mut self,
+        program: &Program,
+        mut input: impl Read,
+        output: impl Write,
+        trace: impl Write,
+    ) -> Result<(), RuntimeError> {
+        let program = &*program.optimized_for(&self.config());
+        let mut output = BufWriter::new(output);
+        let mut trace = BufWriter::new(trace);
+        let positions = program.positions();
+        let mut instruction_pointer = 0;
+
+        self.run_bytecode(
+            program.bytecode(),
+            &mut instruction_pointer,
+            &mut input,
+            &mut output,
+            |machine, executed, _| {
+                let Position { line, column, .. } = positions[executed];
+                let pointer = machine.data_pointer;
+                let value = machine.tape[pointer];
+
+                writeln!(
+                    trace,
+                    r#"{{"line":{line},"column":{column},"pointer":{pointer},"value":{value}}}"#
+                )
+                .map_err(|e| RuntimeErrorKind::Trace(e.kind()))?;
+
+                Ok(false)
+            },
+        )
+        .map_err(|kind| self.locate_error(program, instruction_pointer, kind))?;
+
+        trace.flush().map_err(|e| {
+            let last = positions.len().saturating_sub(1);
+            runtime_error(program, last, RuntimeErrorKind::Trace(e.kind()))
+        })
+    }
+
+    /// Runs a program like [`Self::run_with`], counting how often each instruction runs in `profile`
+    ///
+    /// The profile should be [created](Profile::new) for the same program. It keeps the counts of
+    /// the instructions that ran before an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the program is not [optimized for](Program::is_optimized_for) the machine, since
+    /// the profile counts its bytecode.
+    pub fn run_profiled(
+        &mut self,
+        program: &Program,
+        mut input: impl Read,
+        output: impl Write,
+        profile: &mut Profile,
+    ) -> Result<(), RuntimeError> {
+        self.assert_optimized_for(program);
+        let mut output = BufWriter::new(output);
+        let mut instruction_pointer = 0;
+
+        self.run_bytecode(
+            program.bytecode(),
+            &mut instruction_pointer,
+            &mut input,
+            &mut output,
+            |_, executed, _| {
+                profile.record(executed);
+                Ok(false)
+            },
+        )
+        .map(|_| ())
+        .map_err(|kind| self.locate_error(program, instruction_pointer, kind))
+    }
+
+    /// Runs a program like [`Self::run_with`], compiling it to machine code first on x86-64 Linux
+    ///
+    /// This is a lot faster for programs that run for a while. On other platforms, or if the
+    /// machine code cannot be mapped into memory, the program is interpreted instead.
+    #[cfg(feature = "jit")]
+    pub fn run_jit(
+        &mut self,
+        program: &Program,
+        input: impl Read,
+        output: impl Write,
+    ) -> Result<(), RuntimeError> {
+        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+        {
+            let config = self.config();
+            let program = program.optimized_for(&config);
+            crate::jit::run(self, &config, &program, input, output)
+        }
+
+        #[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+        self.run_with(program, input, output)
+    }
+
+    /// The configuration the machine currently behaves like
+    fn config(&self) -> Config {
+        Config {
+            tape_size: self.tape.len(),
+            start: self.data_pointer,
+            boundary: self.boundary,
+            overflow: self.overflow,
+            eof: self.eof,
+        }
+    }
+
+    /// Panics if a program is not optimized for the machine
+    fn assert_optimized_for(&self, program: &Program) {
+        assert!(
+            program.is_optimized_for(&self.config()),
+            "program is not optimized for the configuration of the machine"
+        );
+    }
+
+    /// Returns the index of the cell `offset` cells away from the data pointer
+    ///
+    /// Depending on the boundary behavior, a cell outside of the tape is an error, wraps around or
+    /// grows the tape, moving the data pointer along if cells are added at the start.
+    #[inline]
+    fn offset_index(&mut self, offset: isize) -> Result<usize, RuntimeErrorKind> {
+        match self.data_pointer.checked_add_signed(offset) {
+            Some(index) if index < self.tape.len() => Ok(index),
+            _ => self.outside_index(offset),
+        }
+    }
+
+    /// The slow path of [`Self::offset_index`], for cells outside of the tape
+    #[cold]
+    pub(crate) fn outside_index(&mut self, offset: isize) -> Result<usize, RuntimeErrorKind> {
+        let length = self.tape.len();
+
+        match self.data_pointer.checked_add_signed(offset) {
+            _ if self.boundary == Boundary::Wrap => {
+                Ok((self.data_pointer as isize + offset).rem_euclid(length as isize) as usize)
+            }
+            Some(index) => {
+                if self.boundary == Boundary::Error {
+                    return Err(RuntimeErrorKind::PointerPastEnd);
+                }
+
+                self.tape.resize(doubled(length, index + 1), C::default());
+                Ok(index)
+            }
+            None => {
+                if self.boundary == Boundary::Error {
+                    return Err(RuntimeErrorKind::PointerPastStart);
+                }
+
+                let missing = offset.unsigned_abs() - self.data_pointer;
+                let extra = doubled(length, length.saturating_add(missing)) - length;
+                self.tape
+                    .splice(0..0, std::iter::repeat_n(C::default(), extra));
+                self.data_pointer += extra;
+
+                Ok(self.data_pointer - offset.unsigned_abs())
+            }
+        }
+    }
+
+    /// Prints the data pointer and the cells around it to stderr for `#`
+    #[cold]
+    pub(crate) fn print_dump(&self) {
+        let start = self.data_pointer.saturating_sub(DUMP_WINDOW);
+        let end = self.data_pointer + DUMP_WINDOW + 1;
+
+        eprintln!(
+            "data pointer at {}\n{}",
+            self.data_pointer,
+            self.dump(start..end)
+        );
+    }
+
+    /// Moves the data pointer by `stride` until it points at a zero cell
+    fn scan(&mut self, stride: isize) -> Result<(), RuntimeErrorKind> {
+        let step = stride.unsigned_abs();
+
+        loop {
+            let pointer = self.data_pointer;
+            let (found, steps) = if stride > 0 {
+                let mut cells = self.tape[pointer..].iter().step_by(step);
+                let found = cells.position(|c| c.is_zero());
+                (found, (self.tape.len() - 1 - pointer) / step)
+            } else {
+                let mut cells = self.tape[..=pointer].iter().rev().step_by(step);
+                let found = cells.position(|c| c.is_zero());
+                (found, pointer / step)
+            };
+
+            if let Some(steps) = found {
+                self.data_pointer = pointer.wrapping_add_signed(steps as isize * stride);
+                return Ok(());
+            }
+
+            // The zero cell lies beyond the end of the tape, which is wrapped around or grown from
+            // the last cell that was searched
+            self.data_pointer = pointer.wrapping_add_signed(steps as isize * stride);
+            self.data_pointer = self.offset_index(stride)?;
+        }
+    }
+
+    /// Runs bytecode until it ends or is paused, leaving the instruction pointer at the instruction
+    /// that caused an error
+    ///
+    /// `step` is called after every instruction with the machine, the index of the instruction and
+    /// the index of the next one, returning whether to pause before the next instruction.
+    fn run_bytecode(
+        &mut self,
+        bytecode: &[Bytecode],
+        instruction_pointer: &mut usize,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        mut step: impl FnMut(&Self, usize, usize) -> Result<bool, RuntimeErrorKind>,
+    ) -> Result<Status, RuntimeErrorKind> {
+        while let Some(code) = bytecode.get(*instruction_pointer) {
+            let mut next = *instruction_pointer + 1;
+
+            match *code {
+                Bytecode::Add(amount) => {
+                    self.tape[self.data_pointer] = self.tape[self.data_pointer]
+                        .add(amount, self.overflow)
+                        .ok_or_else(|| overflow_kind(amount))?
+                }
+                Bytecode::Move(offset) => self.data_pointer = self.offset_index(offset)?,
+                Bytecode::Output => output
+                    .write_all(&[self.tape[self.data_pointer].to_byte()])
+                    .map_err(|e| RuntimeErrorKind::Output(e.kind()))?,
+                Bytecode::Input => {
+                    // Flush first, so prompts are visible before waiting for input
+                    output
+                        .flush()
+                        .map_err(|e| RuntimeErrorKind::Output(e.kind()))?;
+
+                    let mut byte: [u8; 1] = [0; 1];
+                    match input.read_exact(&mut byte) {
+                        Ok(()) => self.tape[self.data_pointer] = C::from_byte(byte[0]),
+                        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                            match self.eof {
+                                Eof::Unchanged => (),
+                                Eof::Zero => self.tape[self.data_pointer] = C::default(),
+                                Eof::MinusOne => self.tape[self.data_pointer] = C::MAX,
+                            }
+                        }
+                        Err(e) => return Err(RuntimeErrorKind::Input(e.kind())),
+                    }
+                }
+                Bytecode::Dump => {
+                    // Flush first, so the dump shows up after the output that came before it
+                    output
+                        .flush()
+                        .map_err(|e| RuntimeErrorKind::Output(e.kind()))?;
+
+                    self.print_dump();
+                }
+                Bytecode::Clear => self.tape[self.data_pointer] = C::default(),
+                Bytecode::MulAdd { offset, factor } => {
+                    let value = self.tape[self.data_pointer];
+                    if !value.is_zero() {
+                        let target = self.offset_index(offset)?;
+                        self.tape[target] = self.tape[target]
+                            .mul_add(value, factor, self.overflow)
+                            .ok_or_else(|| overflow_kind(factor))?;
+                    }
+                }
+                Bytecode::Scan(stride) => self.scan(stride)?,
+                Bytecode::JumpIfZero(target) => {
+                    if self.tape[self.data_pointer].is_zero() {
+                        next = target;
+                    }
+                }
+                Bytecode::JumpIfNonZero(target) => {
+                    if !self.tape[self.data_pointer].is_zero() {
+                        next = target;
+                    }
+                }
+            }
+
+            let pause = step(self, *instruction_pointer, next)?;
+            *instruction_pointer = next;
+
+            if pause {
+                output
+                    .flush()
+                    .map_err(|e| RuntimeErrorKind::Output(e.kind()))?;
+                return Ok(Status::Paused);
+            }
+        }
+
+        if let Err(e) = output.flush() {
+            // Attribute the error to the last instruction, since the program has already ended
+            *instruction_pointer = bytecode.len().saturating_sub(1);
+            return Err(RuntimeErrorKind::Output(e.kind()));
+        }
+
+        Ok(Status::Finished)
+    }
+
+    /// Creates an error pointing at the instruction of the source code that caused it
+    ///
+    /// An operation optimized from several instructions is at the position of the first one, so
+    /// those instructions run again one by one from the current state to find the one that failed.
+    /// A failed operation leaves the tape and data pointer in a state where they fail at the same
+    /// instruction as the original program, since programs only run on machines they are optimized
+    /// for, where loops that could trap are never lowered.
+    #[cold]
+    pub(crate) fn locate_error(
+        &mut self,
+        program: &Program,
+        instruction_pointer: usize,
+        kind: RuntimeErrorKind,
+    ) -> RuntimeError {
+        let error = runtime_error(program, instruction_pointer, kind);
+        let folded = match program.bytecode().get(instruction_pointer) {
+            Some(Bytecode::Add(amount)) => amount.unsigned_abs() > 1,
+            Some(Bytecode::Move(offset)) => offset.unsigned_abs() > 1,
+            Some(Bytecode::MulAdd { .. } | Bytecode::Scan(_)) => true,
+            _ => false,
+        };
+
+        if !folded {
+            return error;
+        }
+
+        // The operations a loop was lowered to all share the position of the loop
+        let end = program.positions()[instruction_pointer..]
+            .iter()
+            .find(|&&position| position != error.position);
+
+        let (bytecode, positions) = compile(&translate(program.instructions()));
+        let first = positions.partition_point(|&position| position < error.position);
+        let last = end.map_or(bytecode.len(), |&end| {
+            positions.partition_point(|&position| position < end)
+        });
+
+        let mut replayed = first;
+        let result = self.run_bytecode(
+            &bytecode,
+            &mut replayed,
+            &mut io::empty(),
+            &mut io::sink(),
+            |_, _, next| Ok(next == last),
+        );
+
+        match result {
+            Err(kind) => RuntimeError {
+                kind,
+                position: positions[replayed],
+            },
+            Ok(_) => error,
+        }
+    }
+}
+
+/// The length of a tape doubled until it holds `needed` cells, which is as long as it gets when
+/// it grows one cell at a time
+fn doubled(length: usize, needed: usize) -> usize {
+    let mut grown = length.max(1);
+    while grown < needed {
+        grown = grown.saturating_mul(2);
+    }
+
+    grown
+}
+
+/// Creates an error pointing at the position of a bytecode instruction
+pub(crate) fn runtime_error(
+    program: &Program,
+    instruction_pointer: usize,
+    kind: RuntimeErrorKind,
+) -> RuntimeError {
+    RuntimeError {
+        kind,
+        position: program
+            .positions()
+            .get(instruction_pointer)
+            .copied()
+            .unwrap_or_default(),
+    }
+}
+
+/// The kind of error for a cell that overflowed after adding an amount
+fn overflow_kind(amount: i64) -> RuntimeErrorKind {
+    if amount < 0 {
+        RuntimeErrorKind::CellUnderflow
+    } else {
+        RuntimeErrorKind::CellOverflow
+    }
+}
+
+impl<C: Cell> Default for Machine<C> {
+    fn default() -> Self {
+        Self::with_config(Config::default())
+    }
+}
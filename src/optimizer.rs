@@ -0,0 +1,235 @@
+use std::mem;
+
+use crate::{
+    config::{Boundary, Config, Overflow},
+    lexer::Position,
+    parser::Instruction,
+};
+
+/// An instruction of the optimized intermediate representation
+///
+/// Runs of the same instruction are folded into a single operation, so `+++++` becomes `Add(5)`
+/// and `<<<` becomes `Move(-3)`. Common loop idioms like `[-]` and `[->+<]` are lowered to
+/// operations that do not need to loop at all.
+///
+/// Like [`Instruction`]s, operations are paired with the [`Position`] of the first token they
+/// originate from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// `+` and `-`: Add a (wrapping) amount to the value of the current cell
+    Add(i64),
+    /// `>` and `<`: Move the data pointer by an amount of cells
+    Move(isize),
+    /// `.`: Output the value of the current cell
+    Output,
+    /// `,`: Replace the value of the current cell with input
+    Input,
+    /// `#`: Print the data pointer and the cells around it to stderr
+    Dump,
+    /// `[-]`: Set the value of the current cell to zero
+    Clear,
+    /// `[->+<]`: Add the value of the current cell multiplied by `factor` to the cell at `offset`
+    ///
+    /// Always followed by a [`Op::Clear`], which ends the lowered loop.
+    MulAdd { offset: isize, factor: i64 },
+    /// `[>]` and `[<<]`: Move the data pointer by `stride` cells until it points at a zero cell
+    Scan(isize),
+    /// `[` and `]`: Loop over a vector of operations, ending at the position of the `]`
+    Loop(Vec<(Op, Position)>, Position),
+}
+
+impl Drop for Op {
+    /// Drops nested loops one after another, since dropping them recursively could overflow the
+    /// stack
+    fn drop(&mut self) {
+        if let Op::Loop(body, _) = self {
+            let mut ops = mem::take(body);
+            while let Some((mut op, _)) = ops.pop() {
+                if let Op::Loop(body, _) = &mut op {
+                    ops.append(body);
+                }
+            }
+        }
+    }
+}
+
+/// Turns a tree of instructions into optimized operations for machines with a configuration
+///
+/// Increments are never folded together with decrements, and moves to the right never with moves
+/// to the left, so every operation overflows or leaves the tape exactly when the original
+/// instructions would. Loops are only lowered if that is exact for the configuration.
+pub fn optimize(instructions: &[(Instruction, Position)], config: &Config) -> Vec<(Op, Position)> {
+    transform(instructions, fold, |ops, body, start, end| {
+        if let [(Op::Move(stride), _)] = body[..] {
+            ops.push((Op::Scan(stride), start));
+        } else if let Some(lowered) = lower_loop(&body, config) {
+            ops.extend(lowered.into_iter().map(|op| (op, start)));
+        } else {
+            ops.push((Op::Loop(body, end), start));
+        }
+    })
+}
+
+/// Turns a tree of instructions into operations one by one, without optimizing them
+///
+/// Every operation corresponds to exactly one instruction, which keeps the program steppable
+/// instruction by instruction.
+pub fn translate(instructions: &[(Instruction, Position)]) -> Vec<(Op, Position)> {
+    transform(
+        instructions,
+        |ops, op, position| ops.push((op, position)),
+        |ops, body, start, end| ops.push((Op::Loop(body, end), start)),
+    )
+}
+
+/// Turns a tree of instructions into operations with an explicit stack instead of recursion, so
+/// deeply nested loops cannot overflow the stack
+///
+/// `push` adds the operation of a single instruction to a block, `close` adds the body of a loop
+/// that ends at the second position.
+fn transform(
+    instructions: &[(Instruction, Position)],
+    push: impl Fn(&mut Vec<(Op, Position)>, Op, Position),
+    close: impl Fn(&mut Vec<(Op, Position)>, Vec<(Op, Position)>, Position, Position),
+) -> Vec<(Op, Position)> {
+    let mut ops = vec![];
+    let mut rest = instructions.iter();
+
+    // The loops that are currently open, with the operations and the instructions around them
+    let mut stack = vec![];
+
+    loop {
+        let Some((instruction, position)) = rest.next() else {
+            let Some((outer, outer_rest, start, end)) = stack.pop() else {
+                return ops;
+            };
+
+            let body = mem::replace(&mut ops, outer);
+            rest = outer_rest;
+            close(&mut ops, body, start, end);
+            continue;
+        };
+
+        let op = match instruction {
+            Instruction::IncrementPointer => Op::Move(1),
+            Instruction::DecrementPointer => Op::Move(-1),
+            Instruction::Increment => Op::Add(1),
+            Instruction::Decrement => Op::Add(-1),
+            Instruction::Output => Op::Output,
+            Instruction::Input => Op::Input,
+            Instruction::Dump => Op::Dump,
+            Instruction::Loop(body, end) => {
+                stack.push((mem::take(&mut ops), rest, *position, *end));
+                rest = body.iter();
+                continue;
+            }
+        };
+
+        push(&mut ops, op, *position);
+    }
+}
+
+/// Adds an operation to a block, folding it into the previous one if they are of the same kind
+fn fold(ops: &mut Vec<(Op, Position)>, op: Op, position: Position) {
+    match (ops.last_mut(), op) {
+        (Some((Op::Add(previous), _)), Op::Add(amount)) if previous.signum() == amount.signum() => {
+            *previous += amount
+        }
+        (Some((Op::Move(previous), _)), Op::Move(amount))
+            if previous.signum() == amount.signum() =>
+        {
+            *previous += amount
+        }
+        (_, op) => ops.push((op, position)),
+    }
+}
+
+/// Lowers a loop that only adds to cells and ends up on the cell it started on
+///
+/// If the current cell is decremented by one every iteration, the loop runs exactly as many times
+/// as the value of the cell, so every other cell simply gets the value multiplied by its change.
+/// Cells that are both incremented and decremented are not lowered, since their intermediate
+/// values could overflow.
+///
+/// The lowered loop checks that each changed cell is on the tape, in the order the loop reaches
+/// them. So the pointer may only turn around on a changed cell, otherwise the loop could leave the
+/// tape at a cell that is never checked. On a tape with edges, the cell farthest away is changed
+/// first, after checking the farthest cell on the other side without changing it. That way the
+/// loop fails before changing any cell, so its first iteration can be run again to find the
+/// instruction that left the tape. On a tape that wraps around, every offset must be a different
+/// cell. Cells that trap on overflow are not lowered at all, since the error has to point at the
+/// instruction of the iteration that overflowed.
+fn lower_loop(body: &[(Op, Position)], config: &Config) -> Option<Vec<Op>> {
+    let mut offset: isize = 0;
+    let (mut lowest, mut highest) = (0, 0);
+    let mut changes: Vec<(isize, i64)> = vec![];
+
+    for (index, (op, _)) in body.iter().enumerate() {
+        match op {
+            Op::Add(amount) => match changes.iter_mut().find(|(o, _)| *o == offset) {
+                Some((_, change)) if change.signum() == amount.signum() => *change += amount,
+                Some(_) => return None,
+                None => changes.push((offset, *amount)),
+            },
+            Op::Move(_) if matches!(body.get(index + 1), Some((Op::Move(_), _))) => return None,
+            Op::Move(amount) => {
+                offset = offset.checked_add(*amount)?;
+                lowest = lowest.min(offset);
+                highest = highest.max(offset);
+            }
+            _ => return None,
+        }
+    }
+
+    if offset != 0 {
+        return None;
+    }
+
+    if config.boundary == Boundary::Wrap && highest.abs_diff(lowest) >= config.tape_size {
+        return None;
+    }
+
+    let counter = changes
+        .iter()
+        .find(|(o, _)| *o == 0)
+        .map_or(0, |(_, change)| *change);
+
+    if counter != -1 || (config.overflow == Overflow::Trap && changes.len() > 1) {
+        return None;
+    }
+
+    let mut lowered: Vec<Op> = changes
+        .into_iter()
+        .filter(|(o, _)| *o != 0)
+        .map(|(offset, factor)| Op::MulAdd { offset, factor })
+        .collect();
+
+    if config.boundary == Boundary::Error {
+        let (near, far) = if highest >= -lowest {
+            (lowest, highest)
+        } else {
+            (highest, lowest)
+        };
+
+        let farthest = lowered
+            .iter()
+            .position(|op| matches!(op, Op::MulAdd { offset, .. } if *offset == far));
+        if let Some(index) = farthest {
+            let op = lowered.remove(index);
+            lowered.insert(0, op);
+        }
+        if near != 0 {
+            lowered.insert(
+                0,
+                Op::MulAdd {
+                    offset: near,
+                    factor: 0,
+                },
+            );
+        }
+    }
+
+    lowered.push(Op::Clear);
+
+    Some(lowered)
+}
@@ -0,0 +1,101 @@
+use std::mem;
+
+use crate::{
+    error::{ParseError, ParseErrorKind},
+    lexer::{Position, Token},
+};
+
+/// A single executable instruction, with loops already resolved into nested instruction lists
+///
+/// Instructions are always paired with the [`Position`] of the token they originate from, loops
+/// with the position of their `[`. The position of the closing `]` is kept inside the loop.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// `>`: Move the instruction pointer to the left (increment)
+    IncrementPointer,
+    /// `<`: Move the instruction pointer to the right (decrement)
+    DecrementPointer,
+    /// `+`: Increment the value of the current cell
+    Increment,
+    /// `-`: Decrement the value of the current cell
+    Decrement,
+    /// `.`: Output the value of the current cell
+    Output,
+    /// `,`: Replace the value of the current cell with input
+    Input,
+    /// `#`: Print the data pointer and the cells around it to stderr
+    Dump,
+    /// `[` and `]`: Loop over a vector of instructions, ending at the position of the `]`
+    Loop(Vec<(Instruction, Position)>, Position),
+}
+
+impl Drop for Instruction {
+    /// Drops nested loops one after another, since dropping them recursively could overflow the
+    /// stack
+    fn drop(&mut self) {
+        if let Instruction::Loop(body, _) = self {
+            let mut instructions = mem::take(body);
+            while let Some((mut instruction, _)) = instructions.pop() {
+                if let Instruction::Loop(body, _) = &mut instruction {
+                    instructions.append(body);
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<Token> for Instruction {
+    /// The error is `()` since the result should simply be ignored, since every character that is not a valid one is a comment in brainfuck.
+    type Error = ();
+
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::IncrementPointer => Ok(Instruction::IncrementPointer),
+            Token::DecrementPointer => Ok(Instruction::DecrementPointer),
+            Token::Increment => Ok(Instruction::Increment),
+            Token::Decrement => Ok(Instruction::Decrement),
+            Token::Output => Ok(Instruction::Output),
+            Token::Input => Ok(Instruction::Input),
+            Token::Dump => Ok(Instruction::Dump),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Turns a list of tokens into a tree of instructions
+///
+/// Returns an error pointing at the first `[` or `]` that has no matching counterpart.
+pub fn parser(tokens: Vec<(Token, Position)>) -> Result<Vec<(Instruction, Position)>, ParseError> {
+    let mut instructions = vec![];
+
+    // The instructions surrounding each loop that is currently open, together with its start
+    let mut loop_stack: Vec<(Position, Vec<(Instruction, Position)>)> = vec![];
+
+    for (token, position) in tokens {
+        match token {
+            Token::LoopOpen => loop_stack.push((position, mem::take(&mut instructions))),
+            Token::LoopClose => match loop_stack.pop() {
+                Some((start, outer)) => {
+                    let body = mem::replace(&mut instructions, outer);
+                    instructions.push((Instruction::Loop(body, position), start));
+                }
+                None => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::UnmatchedLoopClose,
+                        position,
+                    })
+                }
+            },
+            token => instructions.push((token.try_into().unwrap(), position)),
+        }
+    }
+
+    if let Some((position, _)) = loop_stack.pop() {
+        return Err(ParseError {
+            kind: ParseErrorKind::UnclosedLoop,
+            position,
+        });
+    }
+
+    Ok(instructions)
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+
+use crate::{bytecode::Bytecode, lexer::Position, program::Program};
+
+/// The amount of lines, spans and loops listed in a [report](Profile::report)
+const REPORT_LENGTH: usize = 10;
+
+/// The amount of characters of a line, span or loop shown in a report before it is cut off
+const SNIPPET_LENGTH: usize = 60;
+
+/// How often each bytecode instruction of a program ran, collected by
+/// [`Machine::run_profiled`](crate::Machine::run_profiled)
+///
+/// Programs parsed with [`Program::parse_unoptimized`] are profiled instruction by instruction of the
+/// source code, others by their optimized operations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    counts: Vec<u64>,
+}
+
+/// How often a loop was reached and how often its body ran
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoopProfile {
+    /// The position of the `[` of the loop
+    pub start: Position,
+    /// The position of the `]` of the loop
+    pub end: Position,
+    /// How often the loop was reached
+    pub entries: u64,
+    /// How often the body of the loop ran
+    pub iterations: u64,
+}
+
+/// How often a span of consecutive instructions on the same line ran, which all ran equally often
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpanProfile {
+    /// The position of the first instruction of the span
+    pub start: Position,
+    /// The position of the last instruction of the span
+    pub end: Position,
+    /// How many instructions the span consists of
+    pub length: usize,
+    /// How often each instruction of the span ran
+    pub runs: u64,
+}
+
+impl SpanProfile {
+    /// How many instructions of the span ran in total
+    pub fn instructions(&self) -> u64 {
+        self.runs * self.length as u64
+    }
+}
+
+impl Profile {
+    /// Creates an empty profile for a program
+    pub fn new(program: &Program) -> Self {
+        Self {
+            counts: vec![0; program.bytecode().len()],
+        }
+    }
+
+    /// How often each bytecode instruction ran, at the same index as the instruction
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// How many instructions ran in total
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The profile of every loop of the program, in the order of their `[`
+    pub fn loops(&self, program: &Program) -> Vec<LoopProfile> {
+        let positions = program.positions();
+
+        program
+            .bytecode()
+            .iter()
+            .enumerate()
+            .filter_map(|(start, code)| match *code {
+                // The `]` of the loop is right before the target of its `[`, and every iteration
+                // ends by running it once
+                Bytecode::JumpIfZero(target) => Some(LoopProfile {
+                    start: positions[start],
+                    end: positions[target - 1],
+                    entries: self.count(start),
+                    iterations: self.count(target - 1),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The profile of every span of instructions that ran, in the order of the source code
+    ///
+    /// A span ends where the next instruction ran a different amount of times or is on another
+    /// line, so the body of a loop without nested loops is usually a single span.
+    pub fn spans(&self, program: &Program) -> Vec<SpanProfile> {
+        let mut spans: Vec<SpanProfile> = vec![];
+
+        for (&position, &runs) in program.positions().iter().zip(&self.counts) {
+            match spans.last_mut() {
+                Some(span) if span.runs == runs && span.end.line == position.line => {
+                    span.end = position;
+                    span.length += 1;
+                }
+                _ => spans.push(SpanProfile {
+                    start: position,
+                    end: position,
+                    length: 1,
+                    runs,
+                }),
+            }
+        }
+
+        spans.retain(|span| span.runs > 0);
+        spans
+    }
+
+    /// Renders a report of the source lines and spans of instructions that ran the most
+    /// instructions and the loops that ran the most iterations, followed by the source code
+    /// annotated with the amount of instructions each line ran
+    ///
+    /// `name` is used to refer to the source code, usually its path.
+    pub fn report(&self, program: &Program, name: &str, source: &str) -> String {
+        let lines: Vec<&str> = source.lines().collect();
+        let mut report = format!("profile of {name}: {} instructions ran\n", self.total());
+
+        let mut totals: BTreeMap<usize, u64> = BTreeMap::new();
+        for (position, count) in program.positions().iter().zip(&self.counts) {
+            *totals.entry(position.line).or_default() += count;
+        }
+
+        let mut line_counts: Vec<(usize, u64)> = totals.clone().into_iter().collect();
+        line_counts.retain(|(_, count)| *count > 0);
+        line_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        line_counts.truncate(REPORT_LENGTH);
+
+        if !line_counts.is_empty() {
+            let width = count_width(line_counts.iter().map(|(_, count)| *count), "instructions");
+            let line_width = line_counts.iter().map(|(line, _)| line.to_string().len());
+            let line_width = line_width.max().unwrap_or_default().max("line".len());
+
+            report.push_str(&format!(
+                "\nhottest lines:\n{:>width$}  {:>line_width$} | source\n",
+                "instructions", "line"
+            ));
+            for (line, count) in line_counts {
+                let text = lines.get(line - 1).map_or("", |text| text.trim());
+                let text = snippet(text, false);
+                report.push_str(&format!("{count:>width$}  {line:>line_width$} | {text}\n"));
+            }
+        }
+
+        let mut spans = self.spans(program);
+        spans.sort_by(|a, b| (b.instructions().cmp(&a.instructions())).then(a.start.cmp(&b.start)));
+        spans.truncate(REPORT_LENGTH);
+
+        if !spans.is_empty() {
+            let width = count_width(spans.iter().map(SpanProfile::instructions), "instructions");
+            let runs_width = count_width(spans.iter().map(|span| span.runs), "runs");
+            let location_width = spans.iter().map(|span| span.start.to_string().len());
+            let location_width = location_width.max().unwrap_or_default().max("span".len());
+
+            report.push_str(&format!(
+                "\nhottest spans:\n{:>width$}  {:>runs_width$}  {:>location_width$} | source\n",
+                "instructions", "runs", "span"
+            ));
+            for span in spans {
+                let instructions = span.instructions();
+                let runs = span.runs;
+                let location = span.start.to_string();
+                let text = source
+                    .get(span.start.offset..=span.end.offset)
+                    .unwrap_or_default();
+                let text = snippet(text, false);
+
+                report.push_str(&format!(
+                    "{instructions:>width$}  {runs:>runs_width$}  {location:>location_width$} | {text}\n"
+                ));
+            }
+        }
+
+        let mut loops = self.loops(program);
+        loops.retain(|profile| profile.entries > 0);
+        loops.sort_by(|a, b| b.iterations.cmp(&a.iterations).then(a.start.cmp(&b.start)));
+        loops.truncate(REPORT_LENGTH);
+
+        if !loops.is_empty() {
+            let width = count_width(loops.iter().map(|profile| profile.iterations), "iterations");
+            let entries_width = count_width(loops.iter().map(|profile| profile.entries), "entries");
+            let location_width = loops.iter().map(|profile| profile.start.to_string().len());
+            let location_width = location_width.max().unwrap_or_default().max("loop".len());
+
+            report.push_str(&format!(
+                "\nhottest loops:\n{:>width$}  {:>entries_width$}  {:>location_width$} | source\n",
+                "iterations", "entries", "loop"
+            ));
+            for profile in loops {
+                let LoopProfile {
+                    start,
+                    end,
+                    entries,
+                    iterations,
+                } = profile;
+                let location = start.to_string();
+                let text = loop_snippet(start, end, source);
+
+                report.push_str(&format!(
+                    "{iterations:>width$}  {entries:>entries_width$}  {location:>location_width$} | {text}\n"
+                ));
+            }
+        }
+
+        // Lines without instructions have no count, unlike lines whose instructions never ran
+        let width = count_width(totals.values().copied(), "instructions");
+        let line_width = count_width(std::iter::once(lines.len() as u64), "line");
+
+        report.push_str(&format!(
+            "\nannotated source:\n{:>width$}  {:>line_width$} | source\n",
+            "instructions", "line"
+        ));
+        for (index, text) in lines.iter().enumerate() {
+            let line = index + 1;
+            let count = totals.get(&line).map(u64::to_string).unwrap_or_default();
+            let row = format!("{count:>width$}  {line:>line_width$} | {text}");
+            report.push_str(row.trim_end());
+            report.push('\n');
+        }
+
+        report
+    }
+
+    /// Counts that a bytecode instruction ran
+    pub(crate) fn record(&mut self, instruction_pointer: usize) {
+        if let Some(count) = self.counts.get_mut(instruction_pointer) {
+            *count += 1;
+        }
+    }
+
+    fn count(&self, instruction_pointer: usize) -> u64 {
+        self.counts
+            .get(instruction_pointer)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// The width of a column of counts with a header
+fn count_width(counts: impl Iterator<Item = u64>, header: &str) -> usize {
+    counts
+        .map(|count| count.to_string().len())
+        .max()
+        .unwrap_or_default()
+        .max(header.len())
+}
+
+/// The source code of a loop on a single line
+fn loop_snippet(start: Position, end: Position, source: &str) -> String {
+    let text = source
+        .get(start.offset..=end.offset)
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or_default();
+
+    snippet(text, start.line != end.line)
+}
+
+/// Cuts off text that is too long, marking it as cut off if it is or if it continues anyway
+fn snippet(text: &str, continues: bool) -> String {
+    if text.chars().count() > SNIPPET_LENGTH || continues {
+        let cut: String = text.chars().take(SNIPPET_LENGTH).collect();
+        format!("{} ...", cut.trim_end())
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,153 @@
+use std::borrow::Cow;
+
+use crate::{
+    bytecode::{compile, Bytecode},
+    config::{Boundary, Config},
+    error::ParseError,
+    lexer::{lexer, lexer_with, Extensions, Position},
+    optimizer::{optimize, translate, Op},
+    parser::{parser, Instruction},
+};
+
+/// A parsed brainfuck program, ready to be run by a [`Machine`](crate::Machine)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    instructions: Vec<(Instruction, Position)>,
+    ops: Vec<(Op, Position)>,
+    bytecode: Vec<Bytecode>,
+    positions: Vec<Position>,
+    /// The configuration the operations were optimized for, `None` if they are not optimized
+    optimized_for: Option<Config>,
+}
+
+impl Program {
+    /// Lexes and parses brainfuck source code into a program
+    ///
+    /// Returns an error if a loop has no matching beginning or ending. The program is optimized for
+    /// machines with the [default configuration](Config::default), programs for other machines
+    /// should be parsed with [`Self::parse_with_config`]. Otherwise they are optimized again every
+    /// time they run.
+    pub fn parse(source: impl Into<String>) -> Result<Self, ParseError> {
+        Self::parse_with(source, Extensions::default())
+    }
+
+    /// Like [`Self::parse`], but with the given language extensions enabled
+    pub fn parse_with(
+        source: impl Into<String>,
+        extensions: Extensions,
+    ) -> Result<Self, ParseError> {
+        Self::parse_with_config(source, extensions, &Config::default())
+    }
+
+    /// Like [`Self::parse_with`], but optimized for machines with the given configuration
+    ///
+    /// Some optimizations are only exact for some configurations, like lowering a loop that adds to
+    /// cells further apart than the length of a tape that wraps around.
+    pub fn parse_with_config(
+        source: impl Into<String>,
+        extensions: Extensions,
+        config: &Config,
+    ) -> Result<Self, ParseError> {
+        let tokens = lexer_with(source, extensions);
+        let instructions = parser(tokens)?;
+        let ops = optimize(&instructions, config);
+
+        Ok(Self::with_ops(instructions, ops, Some(config.clone())))
+    }
+
+    /// Lexes and parses brainfuck source code into a program without optimizing it
+    ///
+    /// Every bytecode instruction corresponds to exactly one instruction of the source code, so the
+    /// program can be run one instruction at a time, for example with
+    /// [`Machine::run_until`](crate::Machine::run_until).
+    pub fn parse_unoptimized(source: impl Into<String>) -> Result<Self, ParseError> {
+        let tokens = lexer(source);
+        let instructions = parser(tokens)?;
+        let ops = translate(&instructions);
+
+        Ok(Self::with_ops(instructions, ops, None))
+    }
+
+    /// Turns the program into one that is not optimized, like [`Self::parse_unoptimized`]
+    pub fn unoptimized(self) -> Self {
+        let ops = translate(&self.instructions);
+
+        Self::with_ops(self.instructions, ops, None)
+    }
+
+    /// Whether the operations of the program behave exactly like its instructions on machines with
+    /// the given configuration
+    ///
+    /// Unoptimized programs run on every machine. Optimized ones need the boundary and overflow
+    /// behavior they were optimized for, and a tape that wraps around must be at least as long.
+    pub fn is_optimized_for(&self, config: &Config) -> bool {
+        let Some(optimized) = &self.optimized_for else {
+            return true;
+        };
+
+        optimized.boundary == config.boundary
+            && optimized.overflow == config.overflow
+            && (config.boundary != Boundary::Wrap || optimized.tape_size <= config.tape_size)
+    }
+
+    /// The program, optimized again for the given configuration if it is not
+    /// [optimized for it](Self::is_optimized_for)
+    pub(crate) fn optimized_for(&self, config: &Config) -> Cow<'_, Self> {
+        if self.is_optimized_for(config) {
+            return Cow::Borrowed(self);
+        }
+
+        let ops = optimize(&self.instructions, config);
+        Cow::Owned(Self::with_ops(
+            self.instructions.clone(),
+            ops,
+            Some(config.clone()),
+        ))
+    }
+
+    /// The top level instructions of the program
+    pub fn instructions(&self) -> &[(Instruction, Position)] {
+        &self.instructions
+    }
+
+    /// The optimized operations of the program
+    pub fn ops(&self) -> &[(Op, Position)] {
+        &self.ops
+    }
+
+    /// The flat bytecode the program is executed as
+    pub fn bytecode(&self) -> &[Bytecode] {
+        &self.bytecode
+    }
+
+    /// The position in the source code of every bytecode instruction
+    pub fn positions(&self) -> &[Position] {
+        &self.positions
+    }
+
+    fn with_ops(
+        instructions: Vec<(Instruction, Position)>,
+        ops: Vec<(Op, Position)>,
+        optimized_for: Option<Config>,
+    ) -> Self {
+        let (bytecode, positions) = compile(&ops);
+
+        Self {
+            instructions,
+            ops,
+            bytecode,
+            positions,
+            optimized_for,
+        }
+    }
+}
+
+impl From<Vec<(Instruction, Position)>> for Program {
+    /// Optimizes a tree of instructions for machines with the default configuration
+    fn from(instructions: Vec<(Instruction, Position)>) -> Self {
+        let config = Config::default();
+        let ops = optimize(&instructions, &config);
+
+        Self::with_ops(instructions, ops, Some(config))
+    }
+}
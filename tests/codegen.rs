@@ -0,0 +1,316 @@
+//! Programs compiled to every target must behave like running them with `bfi`
+//!
+//! The compilers and runtimes a target needs are optional, targets without them are skipped.
+
+use std::{
+    fs, io,
+    path::PathBuf,
+    process::{self, Command},
+    thread,
+    time::Duration,
+};
+
+use bfi::{codegen, Boundary, Config, Machine, Overflow, Program};
+
+/// A program, the configuration it is compiled with and what it has to print and exit with
+struct Case {
+    name: &'static str,
+    source: &'static str,
+    config: Config,
+    stdout: &'static [u8],
+    stderr: &'static str,
+    code: i32,
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        Case {
+            name: "hello_world",
+            source: include_str!("../examples/hello_world.bf"),
+            config: Config::default(),
+            stdout: b"Hello World!\n",
+            stderr: "",
+            code: 0,
+        },
+        Case {
+            name: "past_start",
+            source: "+.<",
+            config: Config {
+                tape_size: 4,
+                start: 0,
+                ..Config::default()
+            },
+            stdout: b"\x01",
+            stderr: "error: data pointer moved past the start of the tape at 1:3\n",
+            code: 3,
+        },
+        Case {
+            name: "past_end",
+            source: "+[>+]",
+            config: Config {
+                tape_size: 4,
+                start: 1,
+                ..Config::default()
+            },
+            stdout: b"",
+            stderr: "error: data pointer moved past the end of the tape at 1:3\n",
+            code: 3,
+        },
+        Case {
+            name: "growing",
+            source: "<<<<<+>>>>>>>>>>+.",
+            config: Config {
+                tape_size: 2,
+                start: 1,
+                boundary: Boundary::Grow,
+                ..Config::default()
+            },
+            stdout: b"\x01",
+            stderr: "",
+            code: 0,
+        },
+        Case {
+            name: "underflow",
+            source: "+>-",
+            config: Config {
+                overflow: Overflow::Trap,
+                ..Config::default()
+            },
+            stdout: b"",
+            stderr: "error: cell underflowed at 1:3\n",
+            code: 3,
+        },
+        // Operations optimized from several instructions fail at the one that fails on its own
+        Case {
+            name: "merged_past_start",
+            source: "<<<+",
+            config: Config {
+                tape_size: 5,
+                start: 2,
+                ..Config::default()
+            },
+            stdout: b"",
+            stderr: "error: data pointer moved past the start of the tape at 1:3\n",
+            code: 3,
+        },
+        Case {
+            name: "merged_underflow",
+            source: "+---",
+            config: Config {
+                overflow: Overflow::Trap,
+                ..Config::default()
+            },
+            stdout: b"",
+            stderr: "error: cell underflowed at 1:3\n",
+            code: 3,
+        },
+        Case {
+            name: "scan_past_end",
+            source: "+>+>+[>>]",
+            config: Config {
+                tape_size: 4,
+                start: 0,
+                ..Config::default()
+            },
+            stdout: b"",
+            stderr: "error: data pointer moved past the end of the tape at 1:8\n",
+            code: 3,
+        },
+        Case {
+            name: "lowered_overflow",
+            source: "++++++++++++++++[->++++++++++++++++<]",
+            config: Config {
+                overflow: Overflow::Trap,
+                ..Config::default()
+            },
+            stdout: b"",
+            stderr: "error: cell overflowed at 1:35\n",
+            code: 3,
+        },
+    ]
+}
+
+/// A host for `wasm` modules in node, which prints errors like `bfi`
+const HOST: &str = "
+import { readFileSync, writeSync } from 'node:fs';
+const { instance } = await WebAssembly.instantiate(readFileSync(process.argv[2]), {
+  env: { read: () => -1, write: (byte) => writeSync(1, Uint8Array.of(byte)) },
+});
+const code = instance.exports.run();
+const messages = ['', 'data pointer moved past the start of the tape',
+  'data pointer moved past the end of the tape', 'cell overflowed', 'cell underflowed',
+  'failed to grow the tape'];
+if (code != 0) {
+  const { line, column } = instance.exports;
+  writeSync(2, `error: ${messages[code]} at ${line.value}:${column.value}\\n`);
+  process.exitCode = 3;
+}
+";
+
+/// Runs a `wasm-wasi` module in node
+const WASI: &str = "
+import { readFileSync } from 'node:fs';
+import { WASI } from 'node:wasi';
+const wasi = new WASI({ version: 'preview1', returnOnExit: true });
+const module = await WebAssembly.compile(readFileSync(process.argv[2]));
+process.exitCode = wasi.start(await WebAssembly.instantiate(module, wasi.getImportObject()));
+";
+
+/// A path in the temporary directory that no other test run uses
+fn temporary(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bfi-codegen-{}-{name}", process::id()))
+}
+
+/// Whether a program can be run, noting that the test is skipped otherwise
+fn available(program: &str) -> bool {
+    let found = Command::new(program).arg("--version").output().is_ok();
+    if !found {
+        eprintln!("skipped, since `{program}` is not available");
+    }
+
+    found
+}
+
+/// Runs a command and checks its output and exit code against a case
+fn check(case: &Case, command: &mut Command) {
+    // A freshly written executable stays busy while another test starts a process, since the
+    // child holds the file open until it runs its own program
+    let output = loop {
+        match command.output() {
+            Err(error) if error.kind() == io::ErrorKind::ExecutableFileBusy => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            output => break output.unwrap(),
+        }
+    };
+
+    assert_eq!(output.stdout, case.stdout, "stdout of {}", case.name);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        case.stderr,
+        "stderr of {}",
+        case.name
+    );
+    assert_eq!(
+        output.status.code(),
+        Some(case.code),
+        "exit code of {}",
+        case.name
+    );
+}
+
+/// Parses the program of a case, optimized for its configuration
+fn parse(case: &Case) -> Program {
+    Program::parse_with_config(case.source, Default::default(), &case.config).unwrap()
+}
+
+/// Compiles the generated source of every case with a compiler and checks the executable
+fn check_compiled(compiler: &str, extension: &str, generate: impl Fn(&Case) -> String) {
+    if !available(compiler) {
+        return;
+    }
+
+    for case in cases() {
+        let source = temporary(&format!("{}.{extension}", case.name));
+        let executable = temporary(&format!("{}-{extension}", case.name));
+        fs::write(&source, generate(&case)).unwrap();
+
+        let status = Command::new(compiler)
+            .arg("-o")
+            .arg(&executable)
+            .arg(&source)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{compiler} failed for {}", case.name);
+
+        check(&case, &mut Command::new(&executable));
+        let _ = fs::remove_file(source);
+        let _ = fs::remove_file(executable);
+    }
+}
+
+/// Runs the module of every case in node with a script and checks its output
+fn check_wasm(interface: codegen::wasm::Interface, name: &str, script: &str) {
+    if !available("node") {
+        return;
+    }
+
+    let runner = temporary(&format!("{name}.mjs"));
+    fs::write(&runner, script).unwrap();
+
+    for case in cases() {
+        let module = temporary(&format!("{}-{name}.wasm", case.name));
+        let program = parse(&case);
+        let bytes = codegen::wasm::compile::<u8>(&program, &case.config, interface).unwrap();
+        fs::write(&module, bytes).unwrap();
+
+        check(
+            &case,
+            Command::new("node")
+                .arg("--no-warnings")
+                .arg(&runner)
+                .arg(&module),
+        );
+        let _ = fs::remove_file(module);
+    }
+
+    let _ = fs::remove_file(runner);
+}
+
+#[test]
+fn cases_match_bfi() {
+    for case in cases() {
+        let mut machine = Machine::<u8>::with_config(case.config.clone());
+        let mut output = vec![];
+        let result = machine.run_with(&parse(&case), &b""[..], &mut output);
+
+        assert_eq!(output, case.stdout, "stdout of {}", case.name);
+        match result {
+            Ok(()) => assert_eq!(case.code, 0, "exit code of {}", case.name),
+            Err(error) => {
+                assert_eq!(format!("error: {error}\n"), case.stderr, "{}", case.name);
+                assert_eq!(case.code, 3, "exit code of {}", case.name);
+            }
+        }
+    }
+}
+
+#[test]
+fn c_programs_behave_like_bfi() {
+    check_compiled("cc", "c", |case| {
+        codegen::c::transpile::<u8>(&parse(case), &case.config)
+    });
+}
+
+#[test]
+fn rust_programs_behave_like_bfi() {
+    check_compiled("rustc", "rs", |case| {
+        codegen::rust::transpile_program::<u8>(&parse(case), &case.config)
+    });
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+#[test]
+fn x86_64_executables_behave_like_bfi() {
+    use std::os::unix::fs::PermissionsExt;
+
+    for case in cases() {
+        let executable = temporary(&format!("{}-x86_64", case.name));
+        let bytes = codegen::x86_64::compile::<u8>(&parse(&case), &case.config);
+        fs::write(&executable, bytes).unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+
+        check(&case, &mut Command::new(&executable));
+        let _ = fs::remove_file(executable);
+    }
+}
+
+#[test]
+fn wasm_modules_behave_like_bfi() {
+    check_wasm(codegen::wasm::Interface::Host, "host", HOST);
+}
+
+#[test]
+fn wasi_modules_behave_like_bfi() {
+    check_wasm(codegen::wasm::Interface::Wasi, "wasi", WASI);
+}
@@ -0,0 +1,48 @@
+//! Reading after the end of the input must change the current cell like the EOF behavior says
+
+use bfi::{Cell, Config, Eof, Machine, Program};
+
+/// Reads into a cell set to 2 once from the input and once after its end, returning both values,
+/// which must be the same when compiled with the `jit` feature
+fn read_twice<C: Cell>(eof: Eof) -> (C, C) {
+    let program = Program::parse("++,>++,").unwrap();
+    let config = Config {
+        eof,
+        ..Config::default()
+    };
+    let read = |machine: &Machine<C>| {
+        let pointer = machine.data_pointer();
+        (machine.tape()[pointer - 1], machine.tape()[pointer])
+    };
+
+    let mut machine = Machine::<C>::with_config(config.clone());
+    machine.run_with(&program, &b"\x07"[..], vec![]).unwrap();
+
+    #[cfg(feature = "jit")]
+    {
+        let mut compiled = Machine::<C>::with_config(config);
+        compiled.run_jit(&program, &b"\x07"[..], vec![]).unwrap();
+        assert_eq!(read(&compiled), read(&machine), "compiled with {eof:?}");
+    }
+
+    read(&machine)
+}
+
+#[test]
+fn unchanged_keeps_the_cell() {
+    assert_eq!(read_twice::<u8>(Eof::Unchanged), (7, 2));
+    assert_eq!(read_twice::<u32>(Eof::Unchanged), (7, 2));
+}
+
+#[test]
+fn zero_clears_the_cell() {
+    assert_eq!(read_twice::<u8>(Eof::Zero), (7, 0));
+    assert_eq!(read_twice::<u32>(Eof::Zero), (7, 0));
+}
+
+#[test]
+fn minus_one_sets_the_cell_to_its_maximum() {
+    assert_eq!(read_twice::<u8>(Eof::MinusOne), (7, u8::MAX));
+    assert_eq!(read_twice::<u16>(Eof::MinusOne), (7, u16::MAX));
+    assert_eq!(read_twice::<u64>(Eof::MinusOne), (7, u64::MAX));
+}
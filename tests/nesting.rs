@@ -0,0 +1,83 @@
+//! Programs with deeply nested loops, which must not overflow the stack
+
+use std::thread;
+
+use bfi::{format, Machine, Program};
+
+/// A program that enters `depth` nested loops, clears the cell in the innermost one and prints it
+fn nested(depth: usize) -> String {
+    format!("+{}-{}.", "[".repeat(depth), "]".repeat(depth))
+}
+
+/// Runs a function on a thread with a stack far too small for recursing into every loop
+fn with_small_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    thread::Builder::new()
+        .stack_size(64 * 1024)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn runs_a_million_nested_loops() {
+    let program = Program::parse(nested(1_000_000)).unwrap();
+    let mut output = vec![];
+
+    Machine::new()
+        .run_with(&program, &b""[..], &mut output)
+        .unwrap();
+    assert_eq!(output, [0]);
+}
+
+#[test]
+fn runs_a_million_nested_loops_unoptimized() {
+    let program = Program::parse_unoptimized(nested(1_000_000)).unwrap();
+    let mut output = vec![];
+
+    Machine::new()
+        .run_with(&program, &b""[..], &mut output)
+        .unwrap();
+    assert_eq!(output, [0]);
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn runs_a_million_nested_loops_compiled() {
+    let program = Program::parse(nested(1_000_000)).unwrap();
+    let mut output = vec![];
+
+    Machine::new()
+        .run_jit(&program, &b""[..], &mut output)
+        .unwrap();
+    assert_eq!(output, [0]);
+}
+
+#[test]
+fn parses_and_runs_nested_loops_on_a_small_stack() {
+    let output = with_small_stack(|| {
+        let program = Program::parse(nested(100_000)).unwrap();
+        let mut output = vec![];
+        Machine::new()
+            .run_with(&program, &b""[..], &mut output)
+            .unwrap();
+
+        output
+    });
+
+    assert_eq!(output, [0]);
+}
+
+#[test]
+fn formats_nested_loops_on_a_small_stack() {
+    // Every level is indented, so the formatted program grows quadratically with the depth
+    let formatted =
+        with_small_stack(|| format(Program::parse(nested(2_000)).unwrap().instructions()));
+
+    let lines: Vec<&str> = formatted.lines().collect();
+    assert_eq!(lines.len(), 1 + 1_999 + 1 + 1_999 + 1);
+    assert_eq!(lines[0], "+");
+    assert_eq!(lines[1], "[");
+    assert_eq!(lines[2_000], format!("{}[-]", "    ".repeat(1_999)));
+    assert_eq!(lines.last(), Some(&"."));
+}
@@ -0,0 +1,230 @@
+//! Optimized programs must behave exactly like the instructions they were optimized from, for every
+//! configuration of the machine
+
+use bfi::{
+    Boundary, Cell, Config, Eof, Extensions, Machine, Overflow, Program, RuntimeError, Status,
+};
+
+/// The amount of instructions an unoptimized program may run before it is considered endless
+const STEP_LIMIT: usize = 100_000;
+
+/// The input every program reads
+const INPUT: &[u8] = b"\x05\xfe\x01";
+
+/// Programs that run into the edges of the tape and the range of the cells in every way the
+/// optimizer folds or lowers instructions
+const PROGRAMS: &[&str] = &[
+    include_str!("../examples/hello_world.bf"),
+    include_str!("../examples/square.bf"),
+    "+++++-------",
+    "-----+++++++",
+    "<>+.",
+    "><<<<<<<<<>>>>>>>>>>>>>>",
+    "+[>]",
+    "+[<<]",
+    "+>+>+>+<<<[>>]",
+    "+[-<<<+>>>]+[->>>>>>>+<<<<<<<]",
+    "+++[->+>+>+<<<]>.>.>.",
+    "+[->>+<<]+.",
+    "++[->+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++<]>.",
+    "-[->-<]>.",
+    "+[->>><<+<]",
+    "+[->++>>>+<<<<]",
+    ",[->+<],[->-<]>.",
+    ",,,[-].[.,]",
+    "+[[-]>+]",
+    "+[[->+<]>>>+]",
+];
+
+/// The configurations every program is run with
+fn configs() -> Vec<Config> {
+    let config = |tape_size, start, boundary, overflow, eof| Config {
+        tape_size,
+        start,
+        boundary,
+        overflow,
+        eof,
+    };
+
+    vec![
+        Config::default(),
+        config(4, 0, Boundary::Error, Overflow::Wrap, Eof::Unchanged),
+        config(4, 2, Boundary::Error, Overflow::Trap, Eof::Zero),
+        config(5, 2, Boundary::Error, Overflow::Saturate, Eof::MinusOne),
+        config(2, 0, Boundary::Wrap, Overflow::Wrap, Eof::Unchanged),
+        config(3, 1, Boundary::Wrap, Overflow::Trap, Eof::MinusOne),
+        config(7, 6, Boundary::Wrap, Overflow::Saturate, Eof::Zero),
+        config(2, 1, Boundary::Grow, Overflow::Wrap, Eof::Unchanged),
+        config(3, 0, Boundary::Grow, Overflow::Trap, Eof::Zero),
+        config(1, 0, Boundary::Grow, Overflow::Saturate, Eof::MinusOne),
+    ]
+}
+
+/// The state of a machine after running a program, and how the run ended
+#[derive(Debug, PartialEq)]
+struct Outcome<C> {
+    result: Result<(), RuntimeError>,
+    output: Vec<u8>,
+    tape: Vec<C>,
+    data_pointer: usize,
+}
+
+/// Runs a program on a new machine, returning `None` if it does not end within the step limit
+fn run<C: Cell>(program: &Program, config: &Config) -> Option<Outcome<C>> {
+    let mut machine = Machine::<C>::with_config(config.clone());
+    let mut output = vec![];
+    let mut steps = 0;
+
+    let result = match machine.run_until(program, &mut 0, INPUT, &mut output, |_| {
+        steps += 1;
+        steps > STEP_LIMIT
+    }) {
+        Ok(Status::Paused) => return None,
+        Ok(Status::Finished) => Ok(()),
+        Err(error) => Err(error),
+    };
+
+    Some(Outcome {
+        result,
+        output,
+        tape: machine.tape().to_vec(),
+        data_pointer: machine.data_pointer(),
+    })
+}
+
+/// Checks that the optimized program ends exactly like the unoptimized one, down to the position
+/// of an error and the state of the tape, both interpreted and compiled with the `jit` feature
+fn check<C: Cell>(source: &str, config: &Config) {
+    let unoptimized = Program::parse_unoptimized(source).unwrap();
+    let Some(expected) = run::<C>(&unoptimized, config) else {
+        return;
+    };
+
+    // The optimized program runs fewer instructions, so it ends within the limit as well
+    let optimized = Program::parse_with_config(source, Extensions::default(), config).unwrap();
+    let actual = run::<C>(&optimized, config).expect("the optimized program did not end");
+
+    assert_eq!(actual, expected, "{source:?} with {config:?}");
+
+    // A program optimized for the default configuration is optimized again for the machine
+    let mut machine = Machine::<C>::with_config(config.clone());
+    let mut output = vec![];
+    let result = machine.run_with(&Program::parse(source).unwrap(), INPUT, &mut output);
+    let reoptimized = Outcome {
+        result,
+        output,
+        tape: machine.tape().to_vec(),
+        data_pointer: machine.data_pointer(),
+    };
+
+    assert_eq!(
+        reoptimized, expected,
+        "{source:?} with {config:?} optimized again"
+    );
+
+    #[cfg(feature = "jit")]
+    {
+        let mut machine = Machine::<C>::with_config(config.clone());
+        let mut output = vec![];
+        let result = machine.run_jit(&optimized, INPUT, &mut output);
+        let compiled = Outcome {
+            result,
+            output,
+            tape: machine.tape().to_vec(),
+            data_pointer: machine.data_pointer(),
+        };
+
+        assert_eq!(compiled, expected, "{source:?} with {config:?} compiled");
+    }
+}
+
+/// Generates random programs from a fixed seed, so failures are reproducible
+struct Generator(u64);
+
+impl Generator {
+    /// A random number below `bound`
+    fn below(&mut self, bound: usize) -> usize {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+
+    /// A random program of instructions, loops the optimizer recognizes and nested loops
+    fn program(&mut self, depth: usize) -> String {
+        const LOOPS: &[&str] = &[
+            "[-]",
+            "[>]",
+            "[<]",
+            "[>>]",
+            "[<<<]",
+            "[->+<]",
+            "[->>+<<]",
+            "[-<+>]",
+            "[->+>++<<]",
+            "[->-<]",
+            "[->>><<+<]",
+            "[>+<-]",
+            "[-<<<<<+>>>>>]",
+            "[->>+<<<++>]",
+            "[-<<+>>>+<]",
+        ];
+
+        let mut program = String::new();
+        for _ in 0..1 + self.below(8) {
+            match self.below(20) {
+                0..=2 if depth < 3 => {
+                    program.push('[');
+                    program.push_str(&self.program(depth + 1));
+                    program.push(']');
+                }
+                3 | 4 => program.push_str(LOOPS[self.below(LOOPS.len())]),
+                _ => {
+                    let instruction = ["+", "-", "<", ">", ".", ","][self.below(6)];
+                    program.push_str(&instruction.repeat(1 + self.below(6)));
+                }
+            }
+        }
+
+        program
+    }
+}
+
+#[test]
+fn optimized_programs_behave_like_unoptimized_ones() {
+    for config in configs() {
+        for source in PROGRAMS {
+            check::<u8>(source, &config);
+            check::<u16>(source, &config);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "program is not optimized for the configuration of the machine")]
+fn programs_optimized_for_other_machines_cannot_be_resumed() {
+    let program = Program::parse("+[->+<]").unwrap();
+    let mut machine = Machine::<u8>::with_config(Config {
+        tape_size: 1,
+        start: 0,
+        boundary: Boundary::Wrap,
+        ..Config::default()
+    });
+
+    let _ = machine.run_until(&program, &mut 0, INPUT, vec![], |_| false);
+}
+
+#[test]
+fn optimized_random_programs_behave_like_unoptimized_ones() {
+    let mut generator = Generator(0x2545_f491_4f6c_dd1d);
+    let configs = configs();
+
+    for _ in 0..300 {
+        let source = generator.program(0);
+        for config in &configs {
+            check::<u8>(&source, config);
+        }
+        check::<u64>(&source, &configs[generator.below(configs.len())]);
+    }
+}
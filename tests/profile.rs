@@ -0,0 +1,74 @@
+//! Profiles must count how often loops were reached apart from how often their bodies ran
+
+use bfi::{Machine, Position, Profile, Program};
+
+/// Runs an unoptimized program on an empty input, returning it with its profile
+fn profile(source: &str) -> (Program, Profile) {
+    let program = Program::parse_unoptimized(source).unwrap();
+    let mut profile = Profile::new(&program);
+
+    Machine::<u8>::new()
+        .run_profiled(&program, &b""[..], vec![], &mut profile)
+        .unwrap();
+    (program, profile)
+}
+
+/// The line and column of a position, leaving out its byte offset
+fn at(position: Position) -> (usize, usize) {
+    (position.line, position.column)
+}
+
+#[test]
+fn hello_world_matches_the_readme() {
+    let (program, profile) = profile(include_str!("../examples/hello_world.bf"));
+
+    assert_eq!(profile.total(), 390);
+
+    let loops: Vec<_> = profile
+        .loops(&program)
+        .into_iter()
+        .map(|profile| {
+            (
+                at(profile.start),
+                at(profile.end),
+                profile.entries,
+                profile.iterations,
+            )
+        })
+        .collect();
+    assert_eq!(loops, [((3, 11), (3, 42), 1, 10)]);
+
+    let spans: Vec<_> = profile
+        .spans(&program)
+        .into_iter()
+        .map(|span| {
+            (
+                at(span.start),
+                at(span.end),
+                span.length,
+                span.runs,
+                span.instructions(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        spans,
+        [
+            ((3, 1), (3, 11), 11, 1, 11),
+            ((3, 12), (3, 42), 31, 10, 310),
+            ((3, 43), (3, 111), 69, 1, 69),
+        ]
+    );
+}
+
+#[test]
+fn nested_loops_are_entered_once_per_iteration_of_the_outer_loop() {
+    let (program, profile) = profile("++[>+++[-]<-]");
+
+    let loops: Vec<_> = profile
+        .loops(&program)
+        .into_iter()
+        .map(|profile| (at(profile.start), profile.entries, profile.iterations))
+        .collect();
+    assert_eq!(loops, [((1, 3), 1, 2), ((1, 8), 2, 6)]);
+}
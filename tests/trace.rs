@@ -0,0 +1,75 @@
+//! Traced runs must write one JSON line for every instruction or operation that ran
+
+use bfi::{Config, Extensions, Machine, Program};
+
+const SOURCE: &str = "+>++\n[-]";
+
+/// Runs a program on a tape of 4 cells starting at the first one, returning its trace
+fn trace(program: &Program) -> String {
+    let config = Config {
+        tape_size: 4,
+        start: 0,
+        ..Config::default()
+    };
+    let mut trace = vec![];
+
+    Machine::<u8>::with_config(config)
+        .run_traced(program, &b""[..], vec![], &mut trace)
+        .unwrap();
+    String::from_utf8(trace).unwrap()
+}
+
+#[test]
+fn unoptimized_programs_trace_every_instruction_at_its_position() {
+    let program = Program::parse_unoptimized(SOURCE).unwrap();
+
+    assert_eq!(
+        trace(&program),
+        concat!(
+            r#"{"line":1,"column":1,"pointer":0,"value":1}"#,
+            "\n",
+            r#"{"line":1,"column":2,"pointer":1,"value":0}"#,
+            "\n",
+            r#"{"line":1,"column":3,"pointer":1,"value":1}"#,
+            "\n",
+            r#"{"line":1,"column":4,"pointer":1,"value":2}"#,
+            "\n",
+            r#"{"line":2,"column":1,"pointer":1,"value":2}"#,
+            "\n",
+            r#"{"line":2,"column":2,"pointer":1,"value":1}"#,
+            "\n",
+            r#"{"line":2,"column":3,"pointer":1,"value":1}"#,
+            "\n",
+            r#"{"line":2,"column":2,"pointer":1,"value":0}"#,
+            "\n",
+            r#"{"line":2,"column":3,"pointer":1,"value":0}"#,
+            "\n",
+        )
+    );
+}
+
+#[test]
+fn optimized_programs_trace_every_operation_at_its_first_instruction() {
+    let program = Program::parse_with(SOURCE, Extensions::default()).unwrap();
+    let config = Config {
+        tape_size: 4,
+        start: 0,
+        ..Config::default()
+    };
+
+    // The program is optimized again for the smaller tape, which does not change its operations
+    assert!(program.is_optimized_for(&config));
+    assert_eq!(
+        trace(&program),
+        concat!(
+            r#"{"line":1,"column":1,"pointer":0,"value":1}"#,
+            "\n",
+            r#"{"line":1,"column":2,"pointer":1,"value":0}"#,
+            "\n",
+            r#"{"line":1,"column":3,"pointer":1,"value":2}"#,
+            "\n",
+            r#"{"line":2,"column":1,"pointer":1,"value":0}"#,
+            "\n",
+        )
+    );
+}